@@ -4,8 +4,8 @@ use {
     quote::{quote, quote_spanned},
     syn::{
         punctuated::Punctuated, spanned::Spanned, Data, DataStruct, DeriveInput, Expr, ExprArray,
-        ExprLit, GenericArgument, Ident, Lit, Meta, MetaList, MetaNameValue, Path, PathArguments,
-        Token, Type,
+        ExprLit, Field, GenericArgument, Ident, Lit, Meta, MetaList, MetaNameValue, Path,
+        PathArguments, Token, Type,
     },
 };
 
@@ -18,13 +18,21 @@ enum WfrsType {
 struct WfrsData {
     typ: WfrsType,
     field: Option<Ident>,
+    field_name: String,
     valid: bool,
     ids: Vec<u8>,
     names: Vec<String>,
+    field_type: Option<Type>,
     match_branches: Vec<TokenStream2>,
+    untransform_calls: Vec<TokenStream2>,
+    field_map_branches: Vec<TokenStream2>,
     method_calls: Vec<TokenStream2>,
     if_begin: Option<TokenStream2>,
     params: Vec<TokenStream2>,
+    uses_all_params: bool,
+    extra: Option<Ident>,
+    // Key, name and type of every field with `wfrs` id
+    fields: Vec<(u8, Ident, Type)>,
 }
 
 impl Default for WfrsData {
@@ -32,13 +40,20 @@ impl Default for WfrsData {
         WfrsData {
             typ: WfrsType::Transform,
             field: None,
+            field_name: String::new(),
             valid: false,
             ids: vec![],
             names: vec![],
+            field_type: None,
             match_branches: vec![],
+            untransform_calls: vec![],
+            field_map_branches: vec![],
             method_calls: vec![],
             if_begin: None,
             params: vec![],
+            uses_all_params: false,
+            extra: None,
+            fields: vec![],
         }
     }
 }
@@ -65,6 +80,20 @@ fn transform_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
         let name = field.ident.clone().unwrap();
         data.names.clear();
         data.field = Some(name.clone());
+        data.field_name = serde_name(&field);
+        data.field_type = Some(field.ty.clone());
+
+        // Field for keys without `wfrs` id
+        if is_type(&field.ty, "ExtraParams") {
+            if data.extra.is_some() {
+                return quote_spanned! {
+                    name.span() =>
+                    compile_error!("Should have single `ExtraParams` field");
+                };
+            }
+            data.extra = Some(name);
+            continue;
+        }
 
         // todo: check that field implements Transform and show error near field
         // todo: different behaivor for enums, plain types and slices
@@ -107,9 +136,76 @@ fn transform_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
     }
 
     let match_branches = data.match_branches;
+    let field_map_branches = data.field_map_branches;
+    let mut untransform_calls = data.untransform_calls;
+    let (extra_branch, set_source) = match &data.extra {
+        Some(extra) => {
+            // Not edited fields are written as they were in the source
+            for (id, name, field_type) in &data.fields {
+                untransform_calls.push(quote! {
+                    let value = match self.#extra.source_values(#id) {
+                        Some(values) if is_transformed_from::<#field_type>(&self.#name, values) => {
+                            values.to_vec()
+                        }
+                        _ => (&self.#name as &dyn Transform).untransform(),
+                    };
+                    if !value.is_empty() {
+                        params.insert(#id, value);
+                    }
+                });
+            }
+            untransform_calls.push(quote! {
+                for (key, value) in self.#extra.0.iter() {
+                    if params.get(key).is_none() {
+                        params.insert(*key, value.clone());
+                    }
+                }
+                // Keys are kept in the source order, extra keys are placed between known ones
+                // like in files
+                match self.#extra.source() {
+                    Some(source) => params.sort_like(source),
+                    None => params.sort(),
+                }
+            });
+            (
+                quote! {
+                    v => {
+                        inside.#extra.0.insert(*v, value.clone());
+                    }
+                },
+                quote! {
+                    inside.#extra.set_source(params);
+                },
+            )
+        }
+        None => {
+            for (id, name, _) in &data.fields {
+                untransform_calls.push(quote! {
+                    let value = (&self.#name as &dyn Transform).untransform();
+                    if !value.is_empty() {
+                        params.insert(#id, value);
+                    }
+                });
+            }
+            (
+                quote! {
+                    v => (),
+                },
+                quote! {},
+            )
+        }
+    };
     let res = quote! {
+        impl #ident {
+            pub(crate) fn to_param(&self) -> Param {
+                let mut params = Params::new();
+                #( #untransform_calls )*
+                Param::Child(params)
+            }
+        }
+
         impl Transform for Option<#ident> {
-            fn transform(&mut self, params: &[Param]) {
+            fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
                 match self {
                     None => {
                         *self = Some(#ident {
@@ -119,19 +215,29 @@ fn transform_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
                     Some(_) => (),
                 }
 
-                let params = match params.get(0).unwrap() {
-                    Param::Child(child) => child,
-                    _ => panic!("First param should be child param"),
-                };
+                let params = child_param(params)?;
 
                 if let Some(inside) = self {
                     for (key, value) in params.iter() {
                         match key {
                             #( #match_branches )*
-                            v => (),
+                            #extra_branch
                             // v => panic!("Invalid wfrs id '{v}' for type {}", stringify!(#ident)),
                         }
                     }
+                    #set_source
+                }
+                Ok(())
+            }
+
+            fn untransform(&self) -> Vec<Param> {
+                self.iter().map(|inside| inside.to_param()).collect()
+            }
+
+            fn field_map(key: u8) -> Option<FieldMap> {
+                match key {
+                    #( #field_map_branches )*
+                    _ => None,
                 }
             }
         }
@@ -161,30 +267,24 @@ fn preview_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
         data.names.clear();
         data.params.clear();
         data.valid = false;
+        data.uses_all_params = false;
 
-        match &field.ty {
-            Type::Path(path) => {
-                let segment = &path.path.segments[0];
+        if let Type::Path(path) = &field.ty {
+            let segment = &path.path.segments[0];
 
-                if &segment.ident == "Option" {
-                    match &segment.arguments {
-                        PathArguments::AngleBracketed(args) => match &args.args[0] {
-                            GenericArgument::Type(Type::Path(path)) => {
-                                let segment = &path.path.segments[0];
+            if &segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let GenericArgument::Type(Type::Path(path)) = &args.args[0] {
+                        let segment = &path.path.segments[0];
 
-                                if !["ImgId", "NumberInRect", "bool"]
-                                    .contains(&segment.ident.to_string().as_str())
-                                {
-                                    data.valid = true;
-                                }
-                            }
-                            _ => (),
-                        },
-                        _ => (),
+                        if !["ImgId", "NumberInRect", "bool"]
+                            .contains(&segment.ident.to_string().as_str())
+                        {
+                            data.valid = true;
+                        }
                     }
                 }
             }
-            _ => (),
         }
         if !data.valid {
             continue;
@@ -217,7 +317,7 @@ fn preview_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
             }
         }
         let params = &data.params;
-        let mut expr = quote! { res.append( &mut (&inside.#name as &dyn Preview).get_images(all_params, &vec![ #( #params )* ], images)); };
+        let mut expr = quote! { res.append( &mut (&inside.#name as &dyn Preview).get_images(all_params, &[ #( #params )* ], images)); };
         if data.if_begin.is_some() {
             let if_begin = &data.if_begin;
             expr = quote! {
@@ -227,6 +327,13 @@ fn preview_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
                 }
             };
         }
+        if data.uses_all_params {
+            expr = quote! {
+                if let Some(all_params_val) = &all_params {
+                    #expr
+                }
+            };
+        }
 
         data.method_calls.push(expr);
     }
@@ -235,14 +342,12 @@ fn preview_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
         impl Preview for Option<#ident> {
             fn get_images(&self,
                           all_params: &Option<PreviewParams>,
-                          params: &Vec<ParamType>,
-                          images: &Vec<Image>,) -> Vec<ImageWithCoords> {
+                          params: &[ParamType],
+                          images: &[Image],) -> Vec<ImageWithCoords> {
                 let mut res = vec![];
 
                 if let Some(inside) = self {
-                    if let Some(all_params_val) = &all_params {
-                        #( #method_calls )*
-                    }
+                    #( #method_calls )*
                 }
                 res
             }
@@ -254,6 +359,56 @@ fn preview_struct(ident: Ident, s: DataStruct) -> TokenStream2 {
     res
 }
 
+fn is_type(typ: &Type, name: &str) -> bool {
+    match typ {
+        Type::Path(path) => path.path.is_ident(name),
+        _ => false,
+    }
+}
+
+// Name of the field in json, used to show path to the field in errors
+fn serde_name(field: &Field) -> String {
+    for attr in &field.attrs {
+        if !attr.path().is_ident("serde") {
+            continue;
+        }
+        if let Ok(args) = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) {
+            for arg in args {
+                if let Meta::NameValue(MetaNameValue {
+                    path,
+                    value:
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(rename),
+                            ..
+                        }),
+                    ..
+                }) = arg
+                {
+                    if path.is_ident("rename") {
+                        return rename.value();
+                    }
+                }
+            }
+        }
+    }
+
+    // rename_all = "PascalCase"
+    field
+        .ident
+        .as_ref()
+        .unwrap()
+        .to_string()
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
 impl WfrsData {
     fn wfrs_id(&mut self, value: &Expr) -> Result<(), TokenStream2> {
         match value {
@@ -268,9 +423,21 @@ impl WfrsData {
                 }
                 self.ids.push(id);
                 let name = &self.field;
-                self.match_branches.push(
-                    quote! { #id => (&mut inside.#name as &mut dyn Transform).transform(value), },
-                );
+                let field_name = &self.field_name;
+                self.match_branches.push(quote! {
+                    #id => (&mut inside.#name as &mut dyn Transform)
+                        .transform(value)
+                        .map_err(|e| e.in_field(#field_name))?,
+                });
+                let field_type = &self.field_type;
+                self.field_map_branches.push(quote! {
+                    #id => Some(FieldMap {
+                        name: #field_name,
+                        children: <#field_type as Transform>::field_map,
+                    }),
+                });
+                self.fields
+                    .push((id, name.clone().unwrap(), field_type.clone().unwrap()));
             }
             _ => {
                 return Err(quote_spanned! {
@@ -326,10 +493,11 @@ impl WfrsData {
                                                 has_params = true;
                                                 new_params.push(quote! { #token });
                                             } else {
+                                                self.uses_all_params = true;
                                                 new_params.push(quote! { all_params_val.#ident });
                                             }
                                         }
-                                        TokenTree::Literal(literal) => {
+                                        TokenTree::Literal(_) => {
                                             single_literal = true;
                                             new_params.push(quote! { #token });
                                         }
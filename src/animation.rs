@@ -0,0 +1,430 @@
+use {
+    crate::{common::*, miband::Animation, preview::Preview},
+    image::RgbaImage,
+    std::io::Write,
+};
+
+// Used when animation has no speed set
+const DEFAULT_SPEED: u32 = 100;
+// Shorter steps give too many frames when speeds have small common divisor
+const MIN_STEP: u32 = 20;
+// Animations are exported for at most this time, some of them repeat forever
+const MAX_ANIMATION_TIME: u32 = 60_000;
+
+#[derive(Debug, PartialEq)]
+pub struct AnimationFrame {
+    pub image: RgbaImage,
+    pub delay: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AnimatedPreview {
+    pub frames: Vec<AnimationFrame>,
+    // 0 means to repeat forever
+    pub repeat_count: u32,
+}
+
+impl Animation {
+    fn speed(&self) -> u32 {
+        self.speed.unwrap_or(DEFAULT_SPEED).max(1)
+    }
+
+    fn images_count(&self) -> u32 {
+        self.animation_images
+            .as_ref()
+            .and_then(|images| images.images_count)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    // Time of a single pass over all images
+    fn cycle_time(&self) -> u32 {
+        self.speed().saturating_mul(self.images_count())
+    }
+
+    // Repeat count 0 means that animation never stops
+    fn repeats_forever(&self) -> bool {
+        self.repeat_count.unwrap_or_default() == 0
+    }
+
+    // After the last repeat animation stays on its last image
+    pub fn frame_at(&self, time: u32) -> u32 {
+        let frame = time / self.speed();
+        let images_count = self.images_count();
+        if !self.repeats_forever()
+            && frame >= images_count.saturating_mul(self.repeat_count.unwrap())
+        {
+            images_count - 1
+        } else {
+            frame % images_count
+        }
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: u32, b: u32) -> u32 {
+    (a / gcd(a, b)).saturating_mul(b)
+}
+
+// Params after `time` ms of animation
+fn params_after(params: &PreviewParams, time: u32) -> PreviewParams {
+    let mut params = params.clone();
+    params.animation_time = Some(time);
+
+    // Clock keeps ticking while animation is played, values from scenarios can be anything
+    if let Some(hours) = params.hours_24() {
+        let total = (hours as u64 * 3600
+            + params.minutes.unwrap_or_default() as u64 * 60
+            + params.seconds.unwrap_or_default() as u64
+            + time as u64 / 1000)
+            % (24 * 3600);
+        let total = total as u32;
+        params.set_time(total / 3600, total / 60 % 60, total % 60);
+    }
+    params
+}
+
+pub struct TimeLapse {
+    // All times are in minutes, start is counted from midnight
+    pub start: u32,
+    pub step: u32,
+    pub duration: u32,
+    // Time to show every frame in ms
+    pub frame_delay: u32,
+}
+
+impl Default for TimeLapse {
+    fn default() -> Self {
+        TimeLapse {
+            start: 0,
+            step: 10,
+            duration: 24 * 60,
+            frame_delay: 100,
+        }
+    }
+}
+
+const STEPS_GOAL: u32 = 8000;
+const CALORIES_GOAL: u32 = 400;
+const MAX_PULSE: u32 = 200;
+
+// Steps per minute of an ordinary day: sleep, commute, lunch and evening walk
+fn steps_rate(minute: u32) -> u32 {
+    match minute / 60 {
+        0..=6 | 23 => 0,
+        8 | 17 => 35,
+        12 => 25,
+        19 => 70,
+        _ => 5,
+    }
+}
+
+impl TimeLapse {
+    // Activity values change as they could during the day, so they are reset at midnight
+    pub fn params_at(params: &PreviewParams, minute: u32) -> PreviewParams {
+        let mut params = params.clone();
+        let minute_of_day = minute % (24 * 60);
+        params.set_time(minute_of_day / 60, minute_of_day % 60, 0);
+
+        let steps = (0..minute_of_day).map(steps_rate).sum::<u32>();
+        let calories = steps / 20;
+        let pulse = match steps_rate(minute_of_day) {
+            0 => 55,
+            70 => 125,
+            rate if rate > 10 => 100,
+            _ => 72,
+        };
+        params.steps = Some(steps);
+        params.steps_progress = Some(steps * 100 / STEPS_GOAL);
+        params.distance = Some(steps as f32 * 0.00075);
+        params.calories = Some(calories);
+        params.calories_progress = Some(calories * 100 / CALORIES_GOAL);
+        params.pulse = Some(pulse);
+        params.heart_progress = Some(pulse * 100 / MAX_PULSE);
+        params.battery = Some(100 - minute_of_day * 96 / (24 * 60));
+        params
+    }
+}
+
+impl<T> Watchface<T>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform + Preview,
+{
+    pub fn render_animation(&self, params: &PreviewParams) -> AnimatedPreview {
+        let animations = self
+            .parameters
+            .as_ref()
+            .map_or(&[][..], |parameters| parameters.animations());
+
+        let step = animations
+            .iter()
+            .map(|animation| animation.speed())
+            .reduce(gcd)
+            .unwrap_or(1000)
+            .max(MIN_STEP);
+        let forever = animations
+            .iter()
+            .any(|animation| animation.repeats_forever());
+        let duration = if forever {
+            animations
+                .iter()
+                .map(|animation| animation.cycle_time())
+                .reduce(lcm)
+                .unwrap_or(step)
+        } else {
+            animations
+                .iter()
+                .map(|animation| {
+                    animation
+                        .cycle_time()
+                        .saturating_mul(animation.repeat_count.unwrap())
+                })
+                .max()
+                .unwrap_or(step)
+        }
+        .min(MAX_ANIMATION_TIME);
+
+        let mut frames: Vec<AnimationFrame> = vec![];
+        for time in (0..duration.max(step)).step_by(step as usize) {
+            let image = self.render_preview(&params_after(params, time));
+            match frames.last_mut() {
+                Some(frame) if frame.image == image => frame.delay += step,
+                _ => frames.push(AnimationFrame { image, delay: step }),
+            }
+        }
+
+        AnimatedPreview {
+            frames,
+            repeat_count: if forever { 0 } else { 1 },
+        }
+    }
+
+    pub fn render_time_lapse(
+        &self,
+        params: &PreviewParams,
+        time_lapse: &TimeLapse,
+    ) -> AnimatedPreview {
+        let frames = (0..time_lapse.duration)
+            .step_by(time_lapse.step.max(1) as usize)
+            .map(|minute| {
+                let minute = (time_lapse.start as u64 + minute as u64) % (24 * 60);
+                AnimationFrame {
+                    image: self.render_preview(&TimeLapse::params_at(params, minute as u32)),
+                    delay: time_lapse.frame_delay,
+                }
+            })
+            .collect();
+
+        AnimatedPreview {
+            frames,
+            repeat_count: 0,
+        }
+    }
+}
+
+impl AnimatedPreview {
+    pub fn write_apng<W: Write>(&self, w: W) -> Result<(), png::EncodingError> {
+        let Some(first) = self.frames.first() else {
+            return Ok(());
+        };
+        let mut enc = png::Encoder::new(w, first.image.width(), first.image.height());
+        enc.set_color(png::ColorType::Rgba);
+        enc.set_depth(png::BitDepth::Eight);
+        enc.set_animated(self.frames.len() as u32, self.repeat_count)?;
+        let mut writer = enc.write_header()?;
+        for frame in &self.frames {
+            writer.set_frame_delay(frame.delay.min(u16::MAX as u32) as u16, 1000)?;
+            writer.write_image_data(frame.image.as_raw())?;
+        }
+        writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::miband::*};
+
+    fn animation(images_count: u32, speed: u32, repeat_count: u32) -> Animation {
+        Animation {
+            animation_images: Some(ImageRange {
+                x: 0,
+                y: 0,
+                image_index: Some(ImgId(0)),
+                images_count: Some(images_count),
+                ..Default::default()
+            }),
+            speed: Some(speed),
+            repeat_count: Some(repeat_count),
+            unknown_v4: None,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn animation_frames() {
+        let once = animation(3, 100, 1);
+        let frames = [0, 99, 100, 250, 300, 1000].map(|time| once.frame_at(time));
+        assert_eq!(frames, [0, 0, 1, 2, 2, 2]);
+
+        let forever = animation(3, 100, 0);
+        let frames = [0, 100, 250, 300, 1000].map(|time| forever.frame_at(time));
+        assert_eq!(frames, [0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn render_and_write_animation() {
+        let pixel = |r: u8| vec![r, 0x00, 0x00, 0xFF];
+        let watchface = Watchface {
+            header: WatchfaceHeader::default(),
+            parameters: Some(MiBandParams {
+                other: Some(Other {
+                    animation: Animations(vec![animation(3, 200, 2)]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            images: (1..=3)
+                .map(|r| Image {
+                    pixels: pixel(r),
+                    width: 1,
+                    height: 1,
+                    bits_per_pixel: 32,
+                    pixel_format: 0x10,
+                })
+                .collect(),
+            source: None,
+        };
+
+        let animated = watchface.render_animation(&PreviewParams::default());
+        assert_eq!(animated.repeat_count, 1);
+        let frames = animated
+            .frames
+            .iter()
+            .map(|frame| (frame.image.get_pixel(0, 0).0[0], frame.delay))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [(1, 200), (2, 200), (3, 200), (1, 200), (2, 200), (3, 200)]
+        );
+
+        let mut bytes = vec![];
+        animated.write_apng(&mut bytes).unwrap();
+        let reader = png::Decoder::new(&bytes[..]).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (6, 1));
+    }
+
+    #[test]
+    fn long_animations_are_cut() {
+        let huge = animation(u32::MAX, u32::MAX, u32::MAX);
+        assert_eq!(huge.cycle_time(), u32::MAX);
+        assert_eq!(huge.frame_at(u32::MAX), 1);
+
+        let watchface = Watchface {
+            header: WatchfaceHeader::default(),
+            parameters: Some(MiBandParams {
+                other: Some(Other {
+                    animation: Animations(vec![animation(30, 1000, 255)]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            images: vec![],
+            source: None,
+        };
+
+        let animated = watchface.render_animation(&PreviewParams::default());
+        assert_eq!(animated.repeat_count, 1);
+        let duration = animated.frames.iter().map(|frame| frame.delay).sum::<u32>();
+        assert_eq!(duration, MAX_ANIMATION_TIME);
+    }
+
+    #[test]
+    fn time_lapse_params() {
+        let params = PreviewParams {
+            time12h: true,
+            ..Default::default()
+        };
+
+        let night = TimeLapse::params_at(&params, 30);
+        assert_eq!(
+            (night.hours, night.minutes, night.am),
+            (Some(12), Some(30), true)
+        );
+        assert_eq!((night.steps, night.battery), (Some(0), Some(98)));
+
+        let evening = TimeLapse::params_at(&params, 20 * 60);
+        assert_eq!((evening.hours, evening.am), (Some(8), false));
+        assert!(evening.steps_progress.unwrap() > 100);
+        assert!(evening.battery.unwrap() < 25);
+
+        let next_day = TimeLapse::params_at(&params, 24 * 60 + 30);
+        assert_eq!(next_day.steps, night.steps);
+    }
+
+    #[test]
+    fn render_time_lapse_frames() {
+        let watchface: Watchface<MiBandParams> = Watchface {
+            header: WatchfaceHeader::default(),
+            parameters: Some(MiBandParams::default()),
+            images: vec![],
+            source: None,
+        };
+
+        let time_lapse = TimeLapse {
+            start: 6 * 60,
+            step: 30,
+            duration: 3 * 60,
+            frame_delay: 50,
+        };
+        let animated = watchface.render_time_lapse(&PreviewParams::default(), &time_lapse);
+        assert_eq!(animated.frames.len(), 6);
+        assert!(animated.frames.iter().all(|frame| frame.delay == 50));
+
+        let time_lapse = TimeLapse {
+            start: u32::MAX,
+            step: u32::MAX,
+            duration: u32::MAX,
+            frame_delay: 50,
+        };
+        let animated = watchface.render_time_lapse(&PreviewParams::default(), &time_lapse);
+        assert_eq!(animated.frames.len(), 1);
+    }
+
+    #[test]
+    fn clock_ticks_while_animation_is_played() {
+        let params = PreviewParams {
+            hours: Some(11),
+            minutes: Some(59),
+            seconds: Some(58),
+            time12h: true,
+            am: false,
+            ..Default::default()
+        };
+
+        let params = params_after(&params, 2500);
+        assert_eq!(
+            (params.hours, params.minutes, params.seconds, params.am),
+            (Some(12), Some(0), Some(0), true)
+        );
+        assert_eq!(params.animation_time, Some(2500));
+
+        let params = PreviewParams {
+            hours: Some(u32::MAX),
+            minutes: Some(u32::MAX),
+            seconds: Some(u32::MAX),
+            ..Default::default()
+        };
+        let params = params_after(&params, u32::MAX);
+        assert!(params.hours.unwrap() < 24);
+        assert!(params.minutes.unwrap() < 60);
+    }
+}
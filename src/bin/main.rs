@@ -1,24 +1,233 @@
 use {
-    image::ImageBuffer,
+    serde::{de::DeserializeOwned, Serialize},
     std::{
         error::Error,
         fs::{self, File},
         io::{BufWriter, ErrorKind},
+        path::{Path, PathBuf},
+    },
+    watchface_rs::{
+        dump_watch_face_bin, parse_watch_face_bin, smallest_image_format, watch_face_bin_layout,
+        write_watch_face_bin, BipUParams, GtrParams, Gts2MiniParams, GtsParams, Image,
+        MiBand4Params, MiBand6Params, MiBandParams, Preview, PreviewParams, TimeLapse, Transform,
+        Watchface, WatchfaceParams,
     },
-    watchface_rs::{parse_watch_face_bin, ImageType, MiBandParams, PreviewParams, Watchface},
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let path = std::env::args().nth(1).expect("no path given");
-    let path = std::path::PathBuf::from(path);
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    // `--device name` selects params of the watch, Mi Band 5 is used by default
+    let device = match args.iter().position(|arg| arg == "--device") {
+        Some(index) => {
+            let device = args.get(index + 1).ok_or("no device given")?.clone();
+            args.drain(index..index + 2);
+            device
+        }
+        None => "miband5".to_string(),
+    };
+    match device.as_str() {
+        "bipu" => run::<BipUParams>(&args),
+        "gtr" => run::<GtrParams>(&args),
+        "gts" => run::<GtsParams>(&args),
+        "gts2mini" => run::<Gts2MiniParams>(&args),
+        "miband4" => run::<MiBand4Params>(&args),
+        "miband5" => run::<MiBandParams>(&args),
+        "miband6" => run::<MiBand6Params>(&args),
+        _ => Err(format!("unknown device {device}").into()),
+    }
+}
+
+fn run<T>(args: &[String]) -> Result<(), Box<dyn Error>>
+where
+    T: WatchfaceParams + Serialize + DeserializeOwned,
+    Option<T>: Transform + Preview,
+{
+    let arg = args.first().expect("no path given");
+    if arg == "pack" {
+        let input = args.get(1).expect("no extracted folder given");
+        return pack::<T>(&PathBuf::from(input), args.get(2).cloned());
+    }
+    if arg == "dump" {
+        let input = args.get(1).expect("no path given");
+        print!("{}", dump_watch_face_bin::<T>(&fs::read(input)?)?);
+        return Ok(());
+    }
+    if arg == "layout" {
+        let input = args.get(1).expect("no path given");
+        print!("{}", watch_face_bin_layout::<T>(&fs::read(input)?)?);
+        return Ok(());
+    }
+    if arg == "timelapse" {
+        let input = args.get(1).expect("no path given");
+        return time_lapse::<T>(&PathBuf::from(input), &time_lapse_args(&args[2..])?);
+    }
+    extract::<T>(&PathBuf::from(arg), &scenarios(&args[1..])?)
+}
+
+// Reads `--scenario file.json` or `--scenario dir` and overrides of single params like `--steps 5000`
+fn scenarios(args: &[String]) -> Result<Vec<(String, PreviewParams)>, Box<dyn Error>> {
+    let mut scenarios = vec![];
+    let mut overrides = vec![];
+    for pair in args.chunks(2) {
+        let [flag, value] = pair else {
+            return Err(format!("no value given for {}", pair[0]).into());
+        };
+        let Some(name) = flag.strip_prefix("--") else {
+            return Err(format!("unexpected argument {flag}").into());
+        };
+        if name != "scenario" {
+            let value = serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(value.clone()));
+            overrides.push((name.replace('-', "_"), value));
+            continue;
+        }
+
+        let path = PathBuf::from(value);
+        let mut paths = vec![];
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                let path = entry?.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+        } else {
+            paths.push(path);
+        }
+        for path in paths {
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            let params = serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| format!("{}: {e}", path.to_str().unwrap()))?;
+            scenarios.push((format!("preview_{name}"), params));
+        }
+    }
+
+    if scenarios.is_empty() {
+        scenarios.push(("preview".to_string(), default_preview_params()));
+    }
+
+    for (_, params) in scenarios.iter_mut() {
+        let mut value = serde_json::to_value(&*params)?;
+        for (name, override_value) in &overrides {
+            value[name] = override_value.clone();
+        }
+        *params = serde_json::from_value(value)?;
+    }
+    Ok(scenarios)
+}
+
+fn pack<T>(input: &Path, output: Option<String>) -> Result<(), Box<dyn Error>>
+where
+    T: WatchfaceParams + Serialize + DeserializeOwned,
+    Option<T>: Transform + Preview,
+{
+    let name = input.file_name().unwrap().to_str().unwrap();
+    let output = output.unwrap_or_else(|| {
+        format!(
+            "{}_rs_packed.bin",
+            name.strip_suffix("_rs_extracted").unwrap_or(name)
+        )
+    });
+
+    println!("Reading {}", input.to_str().unwrap());
+    let json = fs::read_to_string(input.join("watchface.json"))?;
+    let parameters: T = serde_json::from_str(&json)?;
+
+    let mut images = vec![];
+    loop {
+        let path = input.join(format!("{}.png", images.len()));
+        if !path.exists() {
+            break;
+        }
+        let mut image = Image::from(image::open(path)?.into_rgba8());
+        (image.pixel_format, image.bits_per_pixel) = smallest_image_format(&image);
+        images.push(image);
+    }
+
+    // Header of the extracted face is kept, so the packed one is for the same device
+    let header = match fs::read_to_string(input.join("header.json")) {
+        Ok(json) => serde_json::from_str(&json)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => T::default_header()
+            .ok_or("no header.json given and default header of the device is not known")?,
+        Err(e) => return Err(e.into()),
+    };
+
+    let watchface = Watchface {
+        header,
+        parameters: Some(parameters),
+        images,
+        source: None,
+    };
+    fs::write(&output, write_watch_face_bin(&watchface)?)?;
+
+    println!("Written to {output}");
+    Ok(())
+}
+
+// Reads `--start`, `--step` and `--duration` in minutes and `--frame-delay` in ms,
+// single number is the step like before flags were added
+fn time_lapse_args(args: &[String]) -> Result<TimeLapse, Box<dyn Error>> {
+    let mut time_lapse = TimeLapse::default();
+    if let [step] = args {
+        time_lapse.step = step.parse()?;
+        return Ok(time_lapse);
+    }
+    for pair in args.chunks(2) {
+        let [flag, value] = pair else {
+            return Err(format!("no value given for {}", pair[0]).into());
+        };
+        let value = value
+            .parse()
+            .map_err(|e| format!("wrong value {value} of {flag}: {e}"))?;
+        match flag.as_str() {
+            "--start" => time_lapse.start = value,
+            "--step" => time_lapse.step = value,
+            "--duration" => time_lapse.duration = value,
+            "--frame-delay" => time_lapse.frame_delay = value,
+            _ => return Err(format!("unexpected argument {flag}").into()),
+        }
+    }
+    Ok(time_lapse)
+}
+
+fn time_lapse<T>(path: &Path, time_lapse: &TimeLapse) -> Result<(), Box<dyn Error>>
+where
+    T: WatchfaceParams + Serialize + DeserializeOwned,
+    Option<T>: Transform + Preview,
+{
+    let output = format!(
+        "{}_rs_timelapse.png",
+        path.file_stem().unwrap().to_str().unwrap()
+    );
+
+    println!("Reading {}", path.to_str().unwrap());
+    let bytes = fs::read(path)?;
+    let watchface: Watchface<T> = parse_watch_face_bin(&mut &bytes[..])?;
+
+    let animated = watchface.render_time_lapse(&default_preview_params(), time_lapse);
+    animated.write_apng(BufWriter::new(File::create(&output)?))?;
+
+    println!("Written to {output}");
+    Ok(())
+}
+
+fn extract<T>(path: &Path, scenarios: &[(String, PreviewParams)]) -> Result<(), Box<dyn Error>>
+where
+    T: WatchfaceParams + Serialize + DeserializeOwned,
+    Option<T>: Transform + Preview,
+{
     let output = format!(
         "{}_rs_extracted",
         path.file_stem().unwrap().to_str().unwrap()
     );
 
     println!("Reading {}", path.to_str().unwrap());
-    let bytes = fs::read(&path).expect("no file found");
-    let watchface: Watchface<MiBandParams> = parse_watch_face_bin(&mut &bytes[..]).unwrap();
+    let bytes = fs::read(path).expect("no file found");
+    let watchface: Watchface<T> = parse_watch_face_bin(&mut &bytes[..])?;
     let res = serde_json::to_string_pretty(&watchface.parameters).unwrap();
 
     // TODO: Probably better to show error to not remove existing, probably modified, extracted watchface folder
@@ -30,6 +239,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     };
 
     fs::write(format!("{output}/watchface.json"), res).expect("cannot write watchface.json");
+    let header = serde_json::to_string_pretty(&watchface.header)?;
+    fs::write(format!("{output}/header.json"), header).expect("cannot write header.json");
 
     for (i, image) in watchface.images.iter().enumerate() {
         let file = File::create(format!("{output}/{i}.png")).unwrap();
@@ -48,7 +259,26 @@ fn main() -> Result<(), Box<dyn Error>> {
         writer.write_image_data(&image.pixels).unwrap();
     }
 
-    let preview = watchface.generate_preview(Some(PreviewParams {
+    for (name, preview_params) in scenarios {
+        let preview = watchface.render_preview(preview_params);
+        let path = format!("{output}/{name}.png");
+        preview.save(path).expect("Failed to save final image");
+
+        if let Some(parameters) = &watchface.parameters {
+            if !parameters.animations().is_empty() {
+                let animated = watchface.render_animation(preview_params);
+                let file = File::create(format!("{output}/{name}_animated.png"))?;
+                animated.write_apng(BufWriter::new(file))?;
+            }
+        }
+    }
+
+    println!("Written to {output}");
+    Ok(())
+}
+
+fn default_preview_params() -> PreviewParams {
+    PreviewParams {
         hours: Some(12),
         minutes: Some(6),
         seconds: Some(34),
@@ -84,32 +314,6 @@ fn main() -> Result<(), Box<dyn Error>> {
         alarm_on: true,
 
         animation: Some(0),
-        ..Default::default()
-    }));
-
-    // todo: move 126, 294 somwhere to watchface or watchface args
-    let mut final_image = ImageBuffer::from_pixel(126, 294, image::Rgba([0, 0, 0, 255]));
-    for image in preview {
-        match image.image_type {
-            ImageType::Id(id) => {
-                let path = format!("{output}/{}.png", id.0);
-                let img = image::open(path).unwrap().into_rgba8();
-                image::imageops::overlay(&mut final_image, &img, image.x as i64, image.y as i64);
-            }
-            ImageType::Image(image_data) => {
-                let img = ImageBuffer::from_raw(
-                    image_data.width as u32,
-                    image_data.height as u32,
-                    image_data.pixels,
-                )
-                .unwrap();
-                image::imageops::overlay(&mut final_image, &img, image.x as i64, image.y as i64);
-            }
-        }
+        animation_time: None,
     }
-    let path = format!("{output}/preview.png");
-    final_image.save(path).expect("Failed to save final image");
-
-    println!("Written to {output}");
-    Ok(())
 }
@@ -0,0 +1,100 @@
+use {
+    crate::common::*,
+    crate::error::WatchfaceError,
+    crate::miband::*,
+    crate::preview::{ParamType, Preview},
+    derive::{PreviewDerive, TransformDerive},
+    serde::{Deserialize, Serialize},
+    std::fmt::Debug,
+};
+
+// Amazfit Bip U uses sections of Mi Band 5 with 32 bit numbers
+mi_band_params! {
+    pub struct BipUParams {
+        10 => analog_dial_face: AnalogDialFace,
+        14 => week_days_icons: WeekDaysIcons,
+    }
+    impl WatchfaceParams {
+        type Header = WatchfaceHeader;
+
+        const SCREEN_WIDTH: u32 = 320;
+        const SCREEN_HEIGHT: u32 = 302;
+        const VALUE_BITS: u32 = 32;
+    }
+}
+
+// Amazfit GTS 2 mini has the same sections as Bip U on a bigger screen
+mi_band_params! {
+    pub struct Gts2MiniParams {
+        10 => analog_dial_face: AnalogDialFace,
+        14 => week_days_icons: WeekDaysIcons,
+    }
+    impl WatchfaceParams {
+        type Header = WatchfaceHeader;
+
+        const SCREEN_WIDTH: u32 = 348;
+        const SCREEN_HEIGHT: u32 = 442;
+        const VALUE_BITS: u32 = 32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{parser::bin_parser, writer::bin_writer},
+        winnow::stream::Located,
+    };
+
+    #[test]
+    fn negative_values_take_5_bytes() {
+        let bytes: Vec<u8> = [
+            &SIGNATURE[..],
+            &DEFAULT_HEADER,
+            &[
+                0x0D, 0x00, 0x00, 0x00, // Size of biggest param
+                0x0C, 0x00, 0x00, 0x00, // Size of params info: 12
+                0x0a, 0x04, 0x08, 0x0D, 0x10, 0x00, // size of params: 13, imagesCount: 0
+                0x12, 0x04, 0x08, 0x00, 0x10,
+                0x0D, // Background param info, offset 0, size 13
+                // Background param: x: -13, y: 258, imgid: 0
+                0x0a, 0x0B, 0x08, 0xF3, 0xFF, 0xFF, 0xFF, 0x0F, 0x10, 0x82, 0x02, 0x18, 0x00,
+            ],
+        ]
+        .concat();
+
+        let watchface = bin_parser::<BipUParams>(Located::new(&bytes)).unwrap();
+        let background = watchface.parameters.as_ref().unwrap().background.as_ref();
+        let image = background.unwrap().image.as_ref().unwrap();
+        assert_eq!((image.x, image.y), (-13, 258));
+
+        let watchface = Watchface {
+            source: None,
+            ..watchface
+        };
+        assert_eq!(bin_writer(&watchface).unwrap(), bytes);
+    }
+
+    #[test]
+    fn preview_gts2mini_bin() {
+        let bytes: Vec<u8> = [
+            &SIGNATURE[..],
+            &DEFAULT_HEADER,
+            &[
+                0x09, 0x00, 0x00, 0x00, // Size of biggest param
+                0x0C, 0x00, 0x00, 0x00, // Size of params info: 12
+                0x0a, 0x04, 0x08, 0x09, 0x10, 0x00, // size of params: 9, imagesCount: 0
+                0x12, 0x04, 0x08, 0x00, 0x10, 0x09, // Background param info, offset 0, size 9
+                // Background param: x: 0, y: 0, color: red
+                0x0a, 0x04, 0x08, 0x00, 0x10, 0x00, 0x10, 0xFF, 0x01,
+            ],
+        ]
+        .concat();
+
+        let watchface = bin_parser::<Gts2MiniParams>(Located::new(&bytes)).unwrap();
+        let preview = watchface.render_preview(&PreviewParams::default());
+        assert_eq!(preview.dimensions(), (348, 442));
+        assert_eq!(preview.get_pixel(347, 441).0, [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(bin_writer(&watchface).unwrap(), bytes);
+    }
+}
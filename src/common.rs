@@ -1,11 +1,136 @@
 use {
-    crate::preview::Preview,
+    crate::{
+        error::WatchfaceError,
+        miband::Animation,
+        parser::{dial_header_parser, header_parser, Stream},
+        preview::Preview,
+        writer::{dial_header_writer, header_writer},
+    },
     derive::TransformDerive,
-    serde::{Deserialize, Serialize},
-    std::{collections::HashMap, fmt::Debug},
+    image::{Rgba, RgbaImage},
+    serde::{
+        de::{DeserializeOwned, MapAccess, Visitor},
+        Deserialize, Serialize,
+    },
+    std::{fmt::Debug, ops::Index},
 };
 
-pub type Params = HashMap<u8, Vec<Param>>;
+pub const SIGNATURE: &[u8; 4] = b"UIHH";
+pub const DIAL_SIGNATURE: &[u8; 7] = b"HMDIAL\0";
+
+// Header copied from a Mi Band 5 watchface, it is accepted by the band as is
+pub const DEFAULT_HEADER: [u8; 75] = [
+    0x01, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0xb5, 0xe5, 0x3d, 0x00, 0x3d, 0x00, 0x30, 0x27,
+    0x00, 0x00, 0xab, 0x86, 0x09, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+// Meaning of the fields is guessed from known watchfaces, all other bytes are kept in raw.
+// Unknown fields change from face to face (maybe checksum and size), they are kept as they are
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WatchfaceHeader {
+    #[serde(with = "hex_bytes")]
+    pub signature: [u8; 4],
+    pub version: u16,
+    pub format_id: u8,
+    pub unknown8: u32,
+    pub device_id: u16,
+    pub watchface_id: u32,
+    pub unknown18: u32,
+    #[serde(with = "hex_bytes")]
+    pub raw: [u8; 75],
+}
+
+impl Default for WatchfaceHeader {
+    fn default() -> Self {
+        WatchfaceHeader {
+            signature: *SIGNATURE,
+            version: 1,
+            format_id: 1,
+            unknown8: 0x3DE5B5,
+            device_id: 0x3D,
+            watchface_id: 10032,
+            unknown18: 0x0986AB,
+            raw: DEFAULT_HEADER,
+        }
+    }
+}
+
+// Start of a file before buffer size, watch families have different ones
+pub trait FileHeader: Debug + PartialEq + Default + Serialize + DeserializeOwned {
+    const SIGNATURE: &'static [u8];
+
+    fn parse(i: &mut Stream) -> Result<Self, WatchfaceError>;
+    fn write(&self) -> Vec<u8>;
+}
+
+impl FileHeader for WatchfaceHeader {
+    const SIGNATURE: &'static [u8] = SIGNATURE;
+
+    fn parse(i: &mut Stream) -> Result<Self, WatchfaceError> {
+        header_parser(i)
+    }
+
+    fn write(&self) -> Vec<u8> {
+        header_writer(self)
+    }
+}
+
+// Header of Amazfit GTR, GTS and Mi Band 4 watchfaces, meaning of bytes after the signature is unknown
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DialHeader {
+    #[serde(with = "hex_bytes")]
+    pub raw: [u8; 25],
+}
+
+impl Default for DialHeader {
+    fn default() -> Self {
+        DialHeader { raw: [0xFF; 25] }
+    }
+}
+
+// Byte arrays of headers are written as hex strings, serde supports only short arrays
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let hex = bytes.iter().map(|byte| format!("{byte:02X}"));
+        serializer.serialize_str(&hex.collect::<String>())
+    }
+
+    pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>();
+        bytes
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| D::Error::custom(format!("expected {N} bytes in hex, got {hex}")))
+    }
+}
+
+impl FileHeader for DialHeader {
+    const SIGNATURE: &'static [u8] = DIAL_SIGNATURE;
+
+    fn parse(i: &mut Stream) -> Result<Self, WatchfaceError> {
+        dial_header_parser(i)
+    }
+
+    fn write(&self) -> Vec<u8> {
+        dial_header_writer(self)
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct Watchface<T>
@@ -13,12 +138,46 @@ where
     T: WatchfaceParams,
     Option<T>: Transform + Preview,
 {
+    pub header: T::Header,
     pub parameters: Option<T>,
     pub images: Vec<Image>,
+    // Parsed file parts, not edited ones are written back byte to byte
+    pub source: Option<WatchfaceSource>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WatchfaceSource {
+    // Untransformed parameters, to find out if they were edited
+    pub parameters: Vec<Param>,
+    // Buffer size, parameters info and parameters
+    pub parameters_bytes: Vec<u8>,
+    // Blocks of every section key, not edited sections are copied when others are edited
+    pub sections: Vec<(u8, Vec<u8>)>,
+    // Decoded images, to find out if they were edited without decoding them again
+    pub images: Vec<Image>,
+    // Every image as it is encoded in the file
+    pub encoded_images: Vec<Vec<u8>>,
+    // Images info, images and everything after them
+    pub images_bytes: Vec<u8>,
 }
 
 pub trait Transform {
-    fn transform(&mut self, params: &[Param]);
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError>;
+    fn untransform(&self) -> Vec<Param>;
+
+    // Field which params with the key are transformed to, None for not mapped keys
+    fn field_map(_key: u8) -> Option<FieldMap>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+// Name of the field in json and the same mapping for keys of its child params
+pub struct FieldMap {
+    pub name: &'static str,
+    pub children: fn(u8) -> Option<FieldMap>,
 }
 
 impl<T> Watchface<T>
@@ -27,11 +186,99 @@ where
     Option<T>: Transform + Preview,
 {
     pub fn generate_preview(&self, params: Option<PreviewParams>) -> Vec<ImageWithCoords> {
-        self.parameters.get_images(&params, &vec![], &self.images)
+        self.parameters.get_images(&params, &[], &self.images)
+    }
+
+    pub fn render_preview(&self, params: &PreviewParams) -> RgbaImage {
+        let background = self
+            .parameters
+            .as_ref()
+            .and_then(|parameters| parameters.background_color())
+            .map_or([0, 0, 0, 255], |color| [color.0, color.1, color.2, 255]);
+        let mut canvas = RgbaImage::from_pixel(T::SCREEN_WIDTH, T::SCREEN_HEIGHT, Rgba(background));
+
+        for image in self.generate_preview(Some(params.clone())) {
+            let image_data = match &image.image_type {
+                ImageType::Id(id) => match self.images.get(id.0 as usize) {
+                    Some(image_data) => image_data,
+                    None => continue,
+                },
+                ImageType::Image(image_data) => image_data,
+            };
+            blend_image(&mut canvas, image_data, image.x, image.y);
+        }
+
+        if T::ROUND_SCREEN {
+            round_mask(&mut canvas);
+        }
+
+        canvas
+    }
+}
+
+// Pixels are transparent out of the circle with one pixel of antialiasing on its border
+fn round_mask(canvas: &mut RgbaImage) {
+    let center_x = canvas.width() as f32 / 2.;
+    let center_y = canvas.height() as f32 / 2.;
+    let radius = center_x.min(center_y);
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        let distance = (x as f32 + 0.5 - center_x).hypot(y as f32 + 0.5 - center_y);
+        let coverage = (radius - distance + 0.5).clamp(0., 1.);
+        pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+    }
+}
+
+// Draws image over canvas with "source over" alpha compositing, parts out of canvas are skipped
+fn blend_image(canvas: &mut RgbaImage, image: &Image, x: i32, y: i32) {
+    for (i, pixel) in image.pixels.chunks_exact(4).enumerate() {
+        let canvas_x = x + (i % image.width as usize) as i32;
+        let canvas_y = y + (i / image.width as usize) as i32;
+        if canvas_x < 0
+            || canvas_y < 0
+            || canvas_x >= canvas.width() as i32
+            || canvas_y >= canvas.height() as i32
+        {
+            continue;
+        }
+
+        let under = canvas.get_pixel_mut(canvas_x as u32, canvas_y as u32);
+        let alpha = pixel[3] as f32 / 255.;
+        let under_alpha = under[3] as f32 / 255. * (1. - alpha);
+        let out_alpha = alpha + under_alpha;
+        if out_alpha == 0. {
+            continue;
+        }
+        for c in 0..3 {
+            under[c] = ((pixel[c] as f32 * alpha + under[c] as f32 * under_alpha) / out_alpha)
+                .round() as u8;
+        }
+        under[3] = (out_alpha * 255.).round() as u8;
     }
 }
 
-pub trait WatchfaceParams {}
+pub trait WatchfaceParams {
+    type Header: FileHeader;
+
+    const SCREEN_WIDTH: u32;
+    const SCREEN_HEIGHT: u32;
+    // Width of numbers in params, negative numbers of 32 bit formats take 5 bytes instead of 10
+    const VALUE_BITS: u32 = 64;
+    // Everything out of the circle inscribed into the screen is not shown
+    const ROUND_SCREEN: bool = false;
+
+    // Header for faces packed from scratch, None when the one of the device is not known
+    fn default_header() -> Option<Self::Header> {
+        None
+    }
+
+    fn background_color(&self) -> Option<&Color> {
+        None
+    }
+
+    fn animations(&self) -> &[Animation] {
+        &[]
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub enum ImageType {
@@ -46,7 +293,8 @@ pub struct ImageWithCoords {
     pub image_type: ImageType,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct PreviewParams {
     pub hours: Option<u32>,
     pub minutes: Option<u32>,
@@ -55,6 +303,7 @@ pub struct PreviewParams {
     pub am: bool,
     pub month: Option<u32>,
     pub day: Option<u32>,
+    // 0 is Monday, 6 is Sunday
     pub weekday: Option<u32>,
 
     pub steps: Option<u32>,
@@ -83,16 +332,417 @@ pub struct PreviewParams {
     pub alarm_on: bool,
 
     pub animation: Option<u32>,
+    // Time in ms since animations started, overrides animation with own image for every animation
+    pub animation_time: Option<u32>,
 }
 
-#[derive(Debug, PartialEq)]
+impl PreviewParams {
+    // Offset is in seconds east of UTC, all fields which are not about time are left empty
+    pub fn from_timestamp(timestamp: i64, utc_offset: i32, time12h: bool) -> Self {
+        let local = timestamp + utc_offset as i64;
+        let days = local.div_euclid(86400);
+        let seconds = local.rem_euclid(86400) as u32;
+
+        // Days to civil date without year, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+
+        let mut params = PreviewParams {
+            time12h,
+            month: Some(month as u32),
+            day: Some(day as u32),
+            // 1970-01-01 was Thursday
+            weekday: Some((days + 3).rem_euclid(7) as u32),
+            ..Default::default()
+        };
+        params.set_time(seconds / 3600, seconds / 60 % 60, seconds % 60);
+        params
+    }
+
+    // Hours are written as 1-12 with am flag for 12h time
+    pub fn set_time(&mut self, hours: u32, minutes: u32, seconds: u32) {
+        self.hours = Some(if self.time12h {
+            (hours + 11) % 12 + 1
+        } else {
+            hours
+        });
+        self.minutes = Some(minutes);
+        self.seconds = Some(seconds);
+        self.am = hours < 12;
+    }
+
+    pub fn hours_24(&self) -> Option<u32> {
+        self.hours.map(|hours| match (self.time12h, self.am) {
+            (false, _) => hours,
+            (true, true) => hours % 12,
+            (true, false) => hours % 12 + 12,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Param {
     Number(i64),
     Float(f32),
     Child(Params),
 }
 
-#[derive(Debug, PartialEq, Default)]
+// Numbers and floats are written as they are, children as objects with keys as names
+impl Serialize for Param {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Param::Number(v) => serializer.serialize_i64(*v),
+            Param::Float(v) => serializer.serialize_f32(*v),
+            Param::Child(v) => v.serialize(serializer),
+        }
+    }
+}
+
+struct ParamVisitor;
+
+impl<'de> Visitor<'de> for ParamVisitor {
+    type Value = Param;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("number, float or object with number keys")
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Param, E> {
+        Ok(Param::Number(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Param, E> {
+        Ok(Param::Number(v as i64))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Param, E> {
+        Ok(Param::Float(v as f32))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Param, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        ParamsVisitor.visit_map(map).map(Param::Child)
+    }
+}
+
+impl<'de> Deserialize<'de> for Param {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ParamVisitor)
+    }
+}
+
+// Byte range of a param in the parsed file, from its key to the end of its value
+// Sections have no keys, so their spans are just their blocks
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParamsEntry {
+    pub key: u8,
+    pub values: Vec<Param>,
+    // Span of every value, empty if params are not parsed from a file
+    pub spans: Vec<Span>,
+    // Place of every value among all values of the params, as keys can be interleaved
+    pub positions: Vec<usize>,
+}
+
+// Params in order of the keys first appearance, values of the same key are kept together
+// and their positions keep the order of all values
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    entries: Vec<ParamsEntry>,
+}
+
+// Spans are only known for parsed params, so they are not compared
+impl PartialEq for Params {
+    fn eq(&self, other: &Self) -> bool {
+        self.ordered().eq(other.ordered())
+    }
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Params::default()
+    }
+
+    pub fn get(&self, key: &u8) -> Option<&Vec<Param>> {
+        self.iter()
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, values)| values)
+    }
+
+    pub fn entries(&self) -> &[ParamsEntry] {
+        &self.entries
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&u8, &Vec<Param>)> {
+        self.entries.iter().map(|entry| (&entry.key, &entry.values))
+    }
+
+    // Every value with its key in the order they were added or parsed
+    pub fn ordered(&self) -> impl Iterator<Item = (u8, &Param)> {
+        let mut values = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry
+                    .positions
+                    .iter()
+                    .zip(&entry.values)
+                    .map(|(position, value)| (*position, entry.key, value))
+            })
+            .collect::<Vec<_>>();
+        values.sort_by_key(|(position, _, _)| *position);
+        values.into_iter().map(|(_, key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &u8> {
+        self.entries.iter().map(|entry| &entry.key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn values_count(&self) -> usize {
+        self.entries.iter().map(|entry| entry.values.len()).sum()
+    }
+
+    // Replaces values of the key keeping their positions, new values are placed at the end
+    pub fn insert(&mut self, key: u8, values: Vec<Param>) {
+        let count = self.values_count();
+        match self.entries.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => {
+                let removed = entry.values.len().saturating_sub(values.len());
+                let added = values.len().saturating_sub(entry.values.len());
+                entry.positions.truncate(values.len());
+                entry.positions.extend(count..count + added);
+                entry.values = values;
+                entry.spans.clear();
+                if removed > 0 {
+                    self.renumber();
+                }
+            }
+            None => self.entries.push(ParamsEntry {
+                key,
+                positions: (count..count + values.len()).collect(),
+                values,
+                spans: vec![],
+            }),
+        }
+    }
+
+    pub fn push(&mut self, key: u8, value: Param, span: Option<Span>) {
+        let count = self.values_count();
+        let index = match self.entries.iter().position(|entry| entry.key == key) {
+            Some(index) => index,
+            None => {
+                self.entries.push(ParamsEntry {
+                    key,
+                    values: vec![],
+                    spans: vec![],
+                    positions: vec![],
+                });
+                self.entries.len() - 1
+            }
+        };
+        let entry = &mut self.entries[index];
+        entry.values.push(value);
+        entry.spans.extend(span);
+        entry.positions.push(count);
+    }
+
+    // Values are ordered by keys, values of the same key keep their order
+    pub fn sort(&mut self) {
+        self.entries.sort_by_key(|entry| entry.key);
+        let mut position = 0;
+        for entry in &mut self.entries {
+            for value_position in &mut entry.positions {
+                *value_position = position;
+                position += 1;
+            }
+        }
+    }
+
+    // Values are ordered like in the source, n-th value of a key takes place of the n-th one
+    // in the source. Other values are placed after values of the same key before the first
+    // greater key
+    pub fn sort_like(&mut self, source: &Params) {
+        // Entry and value index of every value in the new order
+        let mut order = vec![];
+        let mut placed = vec![0; self.entries.len()];
+        for (key, _) in source.ordered() {
+            if let Some(index) = self.entries.iter().position(|entry| entry.key == key) {
+                if placed[index] < self.entries[index].values.len() {
+                    order.push((index, placed[index]));
+                    placed[index] += 1;
+                }
+            }
+        }
+        let mut other = vec![];
+        for (index, entry) in self.entries.iter().enumerate() {
+            other.extend((placed[index]..entry.values.len()).map(|value| (index, value)));
+        }
+        other.sort_by_key(|(index, _)| self.entries[*index].key);
+        for (index, value) in other {
+            let key = self.entries[index].key;
+            let start = order
+                .iter()
+                .rposition(|(other, _)| self.entries[*other].key == key)
+                .map_or(0, |place| place + 1);
+            let place = order[start..]
+                .iter()
+                .position(|(other, _)| self.entries[*other].key > key)
+                .map_or(order.len(), |place| start + place);
+            order.insert(place, (index, value));
+        }
+        for (position, (index, value)) in order.into_iter().enumerate() {
+            self.entries[index].positions[value] = position;
+        }
+        self.entries
+            .sort_by_key(|entry| entry.positions.iter().min().copied());
+    }
+
+    // Positions without gaps after values are removed
+    fn renumber(&mut self) {
+        let mut positions = self
+            .entries
+            .iter_mut()
+            .flat_map(|entry| entry.positions.iter_mut())
+            .collect::<Vec<_>>();
+        positions.sort_by_key(|position| **position);
+        for (index, position) in positions.into_iter().enumerate() {
+            *position = index;
+        }
+    }
+}
+
+impl Index<&u8> for Params {
+    type Output = Vec<Param>;
+
+    fn index(&self, key: &u8) -> &Vec<Param> {
+        self.get(key).expect("no such key in params")
+    }
+}
+
+impl<const N: usize> From<[(u8, Vec<Param>); N]> for Params {
+    fn from(entries: [(u8, Vec<Param>); N]) -> Self {
+        let mut params = Params::new();
+        for (key, values) in entries {
+            params.insert(key, values);
+        }
+        params
+    }
+}
+
+impl Serialize for Params {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+struct ParamsVisitor;
+
+impl<'de> Visitor<'de> for ParamsVisitor {
+    type Value = Params;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("object with number keys")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Params, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut params = Params::new();
+        while let Some((key, value)) = map.next_entry()? {
+            params.insert(key, value);
+        }
+        Ok(params)
+    }
+}
+
+impl<'de> Deserialize<'de> for Params {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ParamsVisitor)
+    }
+}
+
+// Params which are not mapped to fields yet, kept to be written back as they are.
+// Params the struct is transformed from are kept too, so not edited fields and order of keys
+// are written like in the file
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExtraParams(pub Params, #[serde(skip)] Option<Params>);
+
+// Source params are not a part of the struct value
+impl PartialEq for ExtraParams {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl ExtraParams {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn set_source(&mut self, params: &Params) {
+        self.1 = Some(params.clone());
+    }
+
+    pub(crate) fn source(&self) -> Option<&Params> {
+        self.1.as_ref()
+    }
+
+    // Values of the key in the source, None when the struct is not transformed from params
+    pub(crate) fn source_values(&self, key: u8) -> Option<&[Param]> {
+        let source = self.1.as_ref()?;
+        Some(source.get(&key).map_or(&[], |values| &values[..]))
+    }
+}
+
+// Field is not edited when it is the same as the one transformed from the source values
+pub fn is_transformed_from<T>(field: &T, values: &[Param]) -> bool
+where
+    T: Transform + Default + PartialEq,
+{
+    let mut source = T::default();
+    (values.is_empty() || source.transform(values).is_ok()) && source == *field
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
 pub struct Image {
     pub pixels: Vec<u8>,
     pub width: u16,
@@ -101,47 +751,96 @@ pub struct Image {
     pub pixel_format: u16,
 }
 
+impl From<RgbaImage> for Image {
+    fn from(image: RgbaImage) -> Self {
+        Image {
+            width: image.width() as u16,
+            height: image.height() as u16,
+            bits_per_pixel: 32,
+            pixel_format: 0x10,
+            pixels: image.into_raw(),
+        }
+    }
+}
+
+pub fn number_param(params: &[Param]) -> Result<i64, WatchfaceError> {
+    match params.first() {
+        Some(Param::Number(number)) => Ok(*number),
+        Some(_) => Err(WatchfaceError::WrongParamType {
+            path: String::new(),
+            expected: "number",
+        }),
+        None => Err(WatchfaceError::MissingParam {
+            path: String::new(),
+        }),
+    }
+}
+
+pub fn child_param(params: &[Param]) -> Result<&Params, WatchfaceError> {
+    match params.first() {
+        Some(Param::Child(child)) => Ok(child),
+        Some(_) => Err(WatchfaceError::WrongParamType {
+            path: String::new(),
+            expected: "child",
+        }),
+        None => Err(WatchfaceError::MissingParam {
+            path: String::new(),
+        }),
+    }
+}
+
 impl Transform for i32 {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let subvalue = number_param(params)?;
 
-        *self = *subvalue as i32;
+        *self = subvalue as i32;
+        Ok(())
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        vec![Param::Number(*self as i64)]
     }
 }
 
 impl Transform for usize {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let subvalue = number_param(params)?;
+
+        *self = usize::try_from(subvalue).map_err(|_| WatchfaceError::ValueOutOfRange {
+            path: String::new(),
+            value: subvalue,
+        })?;
+        Ok(())
+    }
 
-        *self = *subvalue as usize;
+    fn untransform(&self) -> Vec<Param> {
+        vec![Param::Number(*self as i64)]
     }
 }
 
 impl Transform for Option<u32> {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let subvalue = number_param(params)?;
+
+        *self = Some(subvalue as u32);
+        Ok(())
+    }
 
-        *self = Some(*subvalue as u32);
+    fn untransform(&self) -> Vec<Param> {
+        self.iter().map(|v| Param::Number(*v as i64)).collect()
     }
 }
 
 impl Transform for Option<bool> {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let subvalue = number_param(params)?;
+
+        *self = Some(subvalue != 0);
+        Ok(())
+    }
 
-        *self = Some(*subvalue != 0);
+    fn untransform(&self) -> Vec<Param> {
+        self.iter().map(|v| Param::Number(*v as i64)).collect()
     }
 }
 
@@ -149,13 +848,15 @@ impl Transform for Option<bool> {
 pub struct ImgId(pub u32);
 
 impl Transform for Option<ImgId> {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let subvalue = number_param(params)?;
 
-        *self = Some(ImgId(*subvalue as u32));
+        *self = Some(ImgId(subvalue as u32));
+        Ok(())
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        self.iter().map(|v| Param::Number(v.0 as i64)).collect()
     }
 }
 
@@ -169,6 +870,8 @@ pub struct ImageReference {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -184,6 +887,8 @@ pub struct ImageRange {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub images_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -202,6 +907,8 @@ pub struct StatusPosition {
     #[wfrs(id = 5)]
     #[serde(skip_serializing_if = "Option::is_none")]
     unknown5: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -216,6 +923,8 @@ pub struct StatusImage {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub off_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -225,16 +934,31 @@ pub struct Coordinates {
     pub x: i32,
     #[wfrs(id = 2)]
     pub y: i32,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 impl Transform for Vec<Coordinates> {
-    fn transform(&mut self, params: &[Param]) {
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
         for i in 0..params.len() {
             let param = &params[i..=i]; // heh
             let mut coordinates = None;
-            coordinates.transform(param);
-            self.push(coordinates.unwrap());
+            coordinates
+                .transform(param)
+                .map_err(|e| e.in_field(&format!("[{i}]")))?;
+            self.extend(coordinates);
         }
+        Ok(())
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        self.iter()
+            .map(|coordinates| coordinates.to_param())
+            .collect()
+    }
+
+    fn field_map(key: u8) -> Option<FieldMap> {
+        Option::<Coordinates>::field_map(key)
     }
 }
 
@@ -261,8 +985,7 @@ pub enum AlignmentInternal {
     Center = 72,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
-#[serde(rename_all = "PascalCase")]
+#[derive(Debug, PartialEq)]
 #[repr(u8)]
 pub enum Alignment {
     Unknown = 0, // It probably wrong but I found it in one watchface
@@ -281,17 +1004,45 @@ impl Serialize for Alignment {
         S: serde::Serializer,
     {
         match self {
-            Alignment::Unknown => serializer.serialize_u8(i64::from(Alignment::Unknown) as u8),
+            Alignment::Unknown => serializer.serialize_str("Unknown"),
             Alignment::Valid(v) => serializer.serialize_str(&format!("{v:?}")),
         }
     }
 }
 
+// Numbers are accepted too, Unknown was written as number before
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AlignmentValue {
+    Number(i64),
+    Name(AlignmentInternal),
+}
+
+impl<'de> Deserialize<'de> for Alignment {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = match AlignmentValue::deserialize(deserializer)? {
+            AlignmentValue::Number(v) => v,
+            AlignmentValue::Name(v) => v as i64,
+        };
+        Alignment::try_from(value)
+            .map_err(|_| serde::de::Error::custom(format!("wrong alignment {value}")))
+    }
+}
+
 impl From<Alignment> for i64 {
     fn from(v: Alignment) -> Self {
+        i64::from(&v)
+    }
+}
+
+impl From<&Alignment> for i64 {
+    fn from(v: &Alignment) -> Self {
         match v {
             Alignment::Unknown => 0,
-            Alignment::Valid(v) => v as i64,
+            Alignment::Valid(v) => *v as i64,
         }
     }
 }
@@ -301,7 +1052,7 @@ impl TryFrom<i64> for Alignment {
 
     fn try_from(v: i64) -> Result<Self, Self::Error> {
         match v {
-            x if x == Alignment::Unknown.try_into().unwrap() => Ok(Alignment::Unknown),
+            x if x == i64::from(Alignment::Unknown) => Ok(Alignment::Unknown),
             x if x == AlignmentInternal::Left as i64 => {
                 Ok(Alignment::Valid(AlignmentInternal::Left))
             }
@@ -351,42 +1102,35 @@ impl TryFrom<i64> for Alignment {
 }
 
 impl Transform for Option<Alignment> {
-    fn transform(&mut self, params: &[Param]) {
-        match self {
-            None => {
-                *self = Some(Default::default());
-            }
-            Some(_) => (),
-        }
-
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let mut alignment = Alignment::default();
+        alignment.transform(params)?;
+        *self = Some(alignment);
+        Ok(())
+    }
 
-        if let Some(val) = self.as_mut() {
-            match Alignment::try_from(*subvalue) {
-                Ok(v) => {
-                    *val = v;
-                }
-                Err(_) => panic!("Wrong aligment"),
-            };
-        }
+    fn untransform(&self) -> Vec<Param> {
+        self.iter().map(|v| Param::Number(v.into())).collect()
     }
 }
 impl Transform for Alignment {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        let subvalue = number_param(params)?;
 
-        match Alignment::try_from(*subvalue) {
+        match Alignment::try_from(subvalue) {
             Ok(v) => {
                 *self = v;
+                Ok(())
             }
-            Err(_) => panic!("Wrong aligment"),
-        };
+            Err(_) => Err(WatchfaceError::WrongAlignment {
+                path: String::new(),
+                value: subvalue,
+            }),
+        }
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        vec![Param::Number(self.into())]
     }
 }
 
@@ -413,6 +1157,8 @@ pub struct NumberInRect {
     #[wfrs(id = 9)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub images_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -427,6 +1173,8 @@ pub struct TemperatureType {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -446,23 +1194,20 @@ pub struct VectorShape {
     #[wfrs(id = 5)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub center_image: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
+#[derive(Debug, PartialEq, Default)]
 pub struct Color(pub u8, pub u8, pub u8, pub u8);
 
-impl Transform for Option<Color> {
-    fn transform(&mut self, params: &[Param]) {
-        let subvalue = match params.get(0).unwrap() {
-            Param::Number(number) => number,
-            _ => panic!("First param should be number param"),
-        };
-
+impl From<i64> for Color {
+    fn from(value: i64) -> Self {
         let vals = [
-            (*subvalue >> 24) as u8,
-            (*subvalue >> 16) as u8,
-            (*subvalue >> 8) as u8,
-            *subvalue as u8,
+            (value >> 24) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
         ];
 
         let mut started = false;
@@ -488,7 +1233,7 @@ impl Transform for Option<Color> {
 
         while left > 0 {
             match cur {
-                0 | 1 | 2 => (),
+                0..=2 => (),
                 3 => res.3 = 255,
                 _ => unreachable!(),
             }
@@ -496,38 +1241,229 @@ impl Transform for Option<Color> {
             cur += 1;
         }
 
-        *self = Some(res);
+        res
+    }
+}
+
+impl Transform for Option<Color> {
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        *self = Some(Color::from(number_param(params)?));
+        Ok(())
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        // Alpha 255 is implied when the stored number has only 3 bytes
+        self.iter()
+            .map(|color| {
+                let rgb = (color.0 as i64) << 16 | (color.1 as i64) << 8 | color.2 as i64;
+                if color.3 == 255 {
+                    Param::Number(rgb)
+                } else {
+                    Param::Number(rgb << 8 | color.3 as i64)
+                }
+            })
+            .collect()
     }
 }
 
+// Colors are written as fixed width 0xRRGGBB, or 0xRRGGBBAA when they are not opaque
 impl Serialize for Color {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let vals = [self.0, self.1, self.2, self.3];
-        let mut hex_num = vec![];
-        let mut started = false;
-        let mut first = true;
+        let Color(r, g, b, a) = *self;
+        let hex = if a == 255 {
+            format!("0x{r:02X}{g:02X}{b:02X}")
+        } else {
+            format!("0x{r:02X}{g:02X}{b:02X}{a:02X}")
+        };
+        serializer.serialize_str(&hex)
+    }
+}
 
-        for (i, value) in vals.iter().enumerate() {
-            if !started && *value != 0 {
-                started = true;
-            }
-            if started {
-                if first && *value <= 16 {
-                    hex_num.push(format!("{:X}", value));
-                } else if i != 3 || *value != 255 {
-                    hex_num.push(format!("{:02X}", value));
-                }
-                first = false;
-            }
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        let digits = hex.strip_prefix("0x").unwrap_or(&hex);
+        let byte = |i: usize| {
+            digits
+                .get(i * 2..i * 2 + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        };
+        let color = match digits.len() {
+            6 => byte(0)
+                .zip(byte(1))
+                .zip(byte(2))
+                .map(|((r, g), b)| Color(r, g, b, 255)),
+            8 => byte(0)
+                .zip(byte(1))
+                .zip(byte(2))
+                .zip(byte(3))
+                .map(|(((r, g), b), a)| Color(r, g, b, a)),
+            _ => None,
+        };
+        color.ok_or_else(|| serde::de::Error::custom(format!("wrong color {hex}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_params_from_timestamp() {
+        let date = |params: &PreviewParams| {
+            (
+                params.month,
+                params.day,
+                params.weekday,
+                params.hours,
+                params.minutes,
+                params.seconds,
+                params.am,
+            )
+        };
+
+        let params = PreviewParams::from_timestamp(0, 0, false);
+        assert_eq!(
+            date(&params),
+            (Some(1), Some(1), Some(3), Some(0), Some(0), Some(0), true)
+        );
+
+        let params = PreviewParams::from_timestamp(1709210096, 0, true);
+        assert_eq!(
+            date(&params),
+            (
+                Some(2),
+                Some(29),
+                Some(3),
+                Some(12),
+                Some(34),
+                Some(56),
+                false
+            )
+        );
+
+        // 2024-03-01 03:00 UTC is still February 29 in New York
+        let params = PreviewParams::from_timestamp(1709262000, -5 * 3600, true);
+        assert_eq!(
+            date(&params),
+            (
+                Some(2),
+                Some(29),
+                Some(3),
+                Some(10),
+                Some(0),
+                Some(0),
+                false
+            )
+        );
+
+        let params = PreviewParams::from_timestamp(-1, 0, true);
+        assert_eq!(
+            date(&params),
+            (
+                Some(12),
+                Some(31),
+                Some(2),
+                Some(11),
+                Some(59),
+                Some(59),
+                false
+            )
+        );
+
+        let params = PreviewParams::from_timestamp(1709262000, 9 * 3600, true);
+        assert_eq!(
+            date(&params),
+            (Some(3), Some(1), Some(4), Some(12), Some(0), Some(0), false)
+        );
+    }
+
+    #[test]
+    fn preview_params_from_json() {
+        let params: PreviewParams =
+            serde_json::from_str(r#"{"hours": 23, "time12h": true, "temperature": -5}"#).unwrap();
+        assert_eq!(params.hours, Some(23));
+        assert!(params.time12h);
+        assert_eq!(params.temperature, Some(-5));
+        assert_eq!(params.pulse, None);
+
+        assert!(serde_json::from_str::<PreviewParams>(r#"{"hour": 23}"#).is_err());
+    }
+
+    #[test]
+    fn alignment_json_round_trip() {
+        for value in [0, 2, 4, 8, 16, 32, 64, 18, 34, 66, 20, 36, 68, 24, 40, 72] {
+            let alignment = Alignment::try_from(value).unwrap();
+            let text = serde_json::to_string(&alignment).unwrap();
+            assert_eq!(serde_json::from_str::<Alignment>(&text).unwrap(), alignment);
         }
+        assert_eq!(
+            serde_json::to_string(&Alignment::Unknown).unwrap(),
+            "\"Unknown\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Alignment>("\"Unknown\"").unwrap(),
+            Alignment::Unknown
+        );
+        assert_eq!(
+            serde_json::from_str::<Alignment>("66").unwrap(),
+            Alignment::Valid(AlignmentInternal::CenterLeft)
+        );
+        assert!(serde_json::from_str::<Alignment>("3").is_err());
+    }
 
-        if hex_num.len() == 0 {
-            hex_num.push("0".to_string());
+    #[test]
+    fn color_json_round_trip() {
+        for value in [
+            0, 0x05, 0xFF, 0x1000, 0xFF0000, 0x123456, 0x1000000, 0x123456FF, 0xFFFFFF00,
+        ] {
+            let color = Color::from(value);
+            let text = serde_json::to_string(&color).unwrap();
+            assert_eq!(serde_json::from_str::<Color>(&text).unwrap(), color);
         }
+        assert_eq!(
+            serde_json::from_str::<Color>("\"0xFF8000\"").unwrap(),
+            Color(0xFF, 0x80, 0x00, 0xFF)
+        );
+        assert_eq!(
+            serde_json::from_str::<Color>("\"0x00FF00\"").unwrap(),
+            Color(0x00, 0xFF, 0x00, 0xFF)
+        );
+        assert_eq!(
+            serde_json::from_str::<Color>("\"0x0000FF80\"").unwrap(),
+            Color(0x00, 0x00, 0xFF, 0x80)
+        );
+        assert_eq!(
+            serde_json::to_string(&Color(0x00, 0x00, 0xFF, 0xFF)).unwrap(),
+            "\"0x0000FF\""
+        );
+        assert!(serde_json::from_str::<Color>("\"red\"").is_err());
+        assert!(serde_json::from_str::<Color>("\"0xFF\"").is_err());
+    }
+
+    #[test]
+    fn header_json_round_trip() {
+        let header = WatchfaceHeader {
+            device_id: 0x1234,
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&header).unwrap();
+        assert_eq!(json["Signature"], "55494848");
+        assert_eq!(json["DeviceId"], 0x1234);
+        assert_eq!(
+            serde_json::from_value::<WatchfaceHeader>(json).unwrap(),
+            header
+        );
 
-        serializer.serialize_str(&format!("0x{}", hex_num.concat()))
+        let header = DialHeader { raw: [0x42; 25] };
+        let json = serde_json::to_string(&header).unwrap();
+        assert_eq!(serde_json::from_str::<DialHeader>(&json).unwrap(), header);
+        assert!(serde_json::from_str::<DialHeader>("{\"Raw\": \"4242\"}").is_err());
     }
 }
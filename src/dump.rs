@@ -0,0 +1,120 @@
+use {
+    crate::{
+        common::*, // TODO: not use star
+        error::WatchfaceError,
+        parser::parameters_parser,
+    },
+    std::fmt::Write,
+    winnow::stream::Located,
+};
+
+// Prints every section as a tree of raw params with their offsets and typed fields they map to
+pub fn dump<T>(bytes: &[u8]) -> Result<String, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform,
+{
+    let i = &mut Located::new(bytes);
+    T::Header::parse(i)?;
+    let raw_parameters = parameters_parser(i, T::VALUE_BITS)?;
+
+    let mut res = String::new();
+    for (key, value, span) in sorted_values(&raw_parameters.sections) {
+        let field = Option::<T>::field_map(key);
+        let name = field.as_ref().map_or("UNMAPPED", |field| field.name);
+        writeln!(res, "[{key}] {name} {}", span_str(span)).unwrap();
+        if let Param::Child(params) = value {
+            dump_params(
+                &mut res,
+                params,
+                1,
+                field.as_ref().map(|field| (field, name)),
+            );
+        }
+    }
+    Ok(res)
+}
+
+// Field of params is passed with its dotted path
+fn dump_params(res: &mut String, params: &Params, depth: usize, parent: Option<(&FieldMap, &str)>) {
+    for (key, value, span) in sorted_values(params) {
+        let field = parent.and_then(|(parent, _)| (parent.children)(key));
+        let path = match (&field, parent) {
+            (Some(field), Some((_, parent_path))) => format!("{parent_path}.{}", field.name),
+            _ => "UNMAPPED".to_string(),
+        };
+        let indent = "  ".repeat(depth);
+        let value_str = match value {
+            Param::Number(value) => format!("number {value}"),
+            Param::Float(value) => format!("float {value}"),
+            Param::Child(_) => "child".to_string(),
+        };
+        writeln!(res, "{indent}{key}: {value_str} {} {path}", span_str(span)).unwrap();
+        if let Param::Child(params) = value {
+            let field = field.as_ref().map(|field| (field, path.as_str()));
+            dump_params(res, params, depth + 1, field);
+        }
+    }
+}
+
+// Values in the order they are in the file
+fn sorted_values(params: &Params) -> Vec<(u8, &Param, Option<Span>)> {
+    let mut values = params
+        .entries()
+        .iter()
+        .flat_map(|entry| {
+            entry
+                .values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (entry.key, value, entry.spans.get(index).copied()))
+        })
+        .collect::<Vec<_>>();
+    values.sort_by_key(|(_, _, span)| span.map(|span| span.offset));
+    values
+}
+
+fn span_str(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!("@0x{:x} ({} bytes)", span.offset, span.length),
+        None => "@? (? bytes)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::miband::MiBandParams};
+
+    #[test]
+    fn dump_mapped_and_unmapped_keys() {
+        let bytes: Vec<u8> = [
+            &SIGNATURE[..],
+            &DEFAULT_HEADER,
+            &[
+                0x0B, 0x00, 0x00, 0x00, // Size of biggest param
+                0x12, 0x00, 0x00, 0x00, // Size of params info: 18
+                0x0a, 0x04, 0x08, 0x0D, 0x10, 0x00, // size of params: 13, imagesCount: 0
+                0x12, 0x04, 0x08, 0x00, 0x10,
+                0x0B, // Background param info, offset 0, size 11
+                0x6a, 0x04, 0x08, 0x0B, 0x10, 0x02, // Unknown param info, offset 11, size 2
+                // Background param: x: 1, y: 258, imgid: 0, unknown key 9: 5
+                0x0a, 0x07, 0x08, 0x01, 0x10, 0x82, 0x02, 0x18, 0x00, 0x48, 0x05, 0x08,
+                0x01, // Unknown param
+            ],
+        ]
+        .concat();
+
+        assert_eq!(
+            dump::<MiBandParams>(&bytes).unwrap(),
+            "[2] Background @0x69 (11 bytes)
+  1: child @0x69 (9 bytes) Background.Image
+    1: number 1 @0x6b (2 bytes) Background.Image.X
+    2: number 258 @0x6d (3 bytes) Background.Image.Y
+    3: number 0 @0x70 (2 bytes) Background.Image.ImageIndex
+  9: number 5 @0x72 (2 bytes) UNMAPPED
+[13] UNMAPPED @0x74 (2 bytes)
+  1: number 1 @0x74 (2 bytes) UNMAPPED
+"
+        );
+    }
+}
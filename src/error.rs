@@ -0,0 +1,158 @@
+use std::{error::Error, fmt};
+
+#[derive(Debug, PartialEq)]
+pub enum WatchfaceError {
+    InvalidSignature {
+        signature: Vec<u8>,
+    },
+    UnexpectedEnd {
+        offset: usize,
+    },
+    InvalidImageSignature {
+        offset: usize,
+        signature: u16,
+    },
+    UnsupportedPixelFormat {
+        offset: usize,
+        pixel_format: u16,
+        bits_per_pixel: u16,
+        palette_colors_count: u16,
+    },
+    WrongRowSize {
+        offset: usize,
+        row_size: u16,
+        expected: u16,
+    },
+    WrongColorId {
+        offset: usize,
+        color_id: u8,
+        palette_colors_count: u16,
+    },
+    WrongCompressedData {
+        offset: usize,
+        pixels_count: usize,
+        expected: usize,
+    },
+    WrongParamsSize {
+        offset: usize,
+        size: usize,
+    },
+    MissingParam {
+        path: String,
+    },
+    WrongParamType {
+        path: String,
+        expected: &'static str,
+    },
+    WrongAlignment {
+        path: String,
+        value: i64,
+    },
+    ValueOutOfRange {
+        path: String,
+        value: i64,
+    },
+    TooManyColors {
+        colors_count: usize,
+        bits_per_pixel: u16,
+    },
+}
+
+impl WatchfaceError {
+    // Errors are created at the leaf param and every parent adds its name on the way up
+    pub fn in_field(mut self, field: &str) -> Self {
+        match &mut self {
+            WatchfaceError::MissingParam { path }
+            | WatchfaceError::WrongParamType { path, .. }
+            | WatchfaceError::WrongAlignment { path, .. }
+            | WatchfaceError::ValueOutOfRange { path, .. } => {
+                *path = if path.is_empty() {
+                    field.to_string()
+                } else if path.starts_with('[') {
+                    format!("{field}{path}")
+                } else {
+                    format!("{field}.{path}")
+                };
+            }
+            _ => (),
+        }
+        self
+    }
+}
+
+impl fmt::Display for WatchfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchfaceError::InvalidSignature { signature } => write!(
+                f,
+                "Invalid watchface signature {:?}",
+                String::from_utf8_lossy(signature)
+            ),
+            WatchfaceError::UnexpectedEnd { offset } => {
+                write!(f, "Unexpected end of data at offset {offset}")
+            }
+            WatchfaceError::InvalidImageSignature { offset, signature } => {
+                write!(
+                    f,
+                    "Invalid image signature {signature:#06X} at offset {offset}"
+                )
+            }
+            WatchfaceError::UnsupportedPixelFormat {
+                offset,
+                pixel_format,
+                bits_per_pixel,
+                palette_colors_count,
+            } => write!(
+                f,
+                "Unsupported pixel format {pixel_format:#X} with {bits_per_pixel} bits per pixel and {palette_colors_count} palette colors at offset {offset}"
+            ),
+            WatchfaceError::WrongRowSize {
+                offset,
+                row_size,
+                expected,
+            } => write!(
+                f,
+                "Row size is {row_size} but {expected} expected (padding?) at offset {offset}"
+            ),
+            WatchfaceError::WrongColorId {
+                offset,
+                color_id,
+                palette_colors_count,
+            } => write!(
+                f,
+                "Color {color_id} is out of palette with {palette_colors_count} colors at offset {offset}"
+            ),
+            WatchfaceError::WrongCompressedData {
+                offset,
+                pixels_count,
+                expected,
+            } => write!(
+                f,
+                "Compressed image has {pixels_count} pixels but {expected} expected at offset {offset}"
+            ),
+            WatchfaceError::WrongParamsSize { offset, size } => write!(
+                f,
+                "Param at offset {offset} does not fit into params of size {size}"
+            ),
+            WatchfaceError::MissingParam { path } => write!(f, "{path}: param is missing"),
+            WatchfaceError::WrongParamType { path, expected } => {
+                write!(f, "{path}: {expected} param expected")
+            }
+            WatchfaceError::WrongAlignment { path, value } => {
+                write!(f, "{path}: wrong alignment {value}")
+            }
+            WatchfaceError::ValueOutOfRange { path, value } => {
+                write!(f, "{path}: value {value} is out of range")
+            }
+            WatchfaceError::TooManyColors {
+                colors_count,
+                bits_per_pixel,
+            } => write!(
+                f,
+                "Image has {colors_count} colors which is too many for {bits_per_pixel} bits per pixel palette"
+            ),
+        }
+    }
+}
+
+impl Error for WatchfaceError {}
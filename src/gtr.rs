@@ -0,0 +1,238 @@
+use {
+    crate::common::*,
+    crate::error::WatchfaceError,
+    crate::miband::{
+        mi_band_params, Activity, Alarm, Animation, Background, Battery, CaloriesProgress, Date,
+        HeartProgress, Other, Status, StepsProgress, Time, Weather,
+    },
+    crate::preview::{ParamType, Preview},
+    derive::{PreviewDerive, TransformDerive},
+    serde::{Deserialize, Serialize},
+    std::fmt::Debug,
+};
+
+// Amazfit GTR has a round screen and GTS a rectangular one, both have HMDIAL header
+// and share Mi Band 5 sections except analog hands which can be images and weather forecast.
+// Ids of these sections follow Mi Band 5 ones and are not checked against real faces yet
+mi_band_params! {
+    pub struct GtrParams {
+        10 => analog_dial_face: AnalogDialFace,
+        13 => weather_forecast: Forecast,
+    }
+    impl WatchfaceParams {
+        type Header = DialHeader;
+
+        const SCREEN_WIDTH: u32 = 454;
+        const SCREEN_HEIGHT: u32 = 454;
+        const ROUND_SCREEN: bool = true;
+    }
+}
+
+mi_band_params! {
+    pub struct GtsParams {
+        10 => analog_dial_face: AnalogDialFace,
+        13 => weather_forecast: Forecast,
+    }
+    impl WatchfaceParams {
+        type Header = DialHeader;
+
+        const SCREEN_WIDTH: u32 = 348;
+        const SCREEN_HEIGHT: u32 = 442;
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct AnalogDialFace {
+    #[wfrs(id = 1, params = ["U32", "hours", "F32", "12."])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hours: Option<VectorShape>,
+    #[wfrs(id = 2, params = ["U32", "minutes", "F32", "60."])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minutes: Option<VectorShape>,
+    #[wfrs(id = 3, params = ["U32", "seconds", "F32", "60."])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds: Option<VectorShape>,
+    #[wfrs(id = 4, params = ["U32", "hours", "F32", "12."])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hours_image: Option<ImageHand>,
+    #[wfrs(id = 5, params = ["U32", "minutes", "F32", "60."])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minutes_image: Option<ImageHand>,
+    #[wfrs(id = 6, params = ["U32", "seconds", "F32", "60."])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds_image: Option<ImageHand>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+// Image of the hand points to 12 o'clock and rotates around center of the screen,
+// x and y of the image are coordinates of the center inside the image
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageHand {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center: Option<Coordinates>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageReference>,
+    #[wfrs(id = 3)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center_image: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Forecast {
+    #[wfrs(id = 1)]
+    pub days: Vec<ForecastDay>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct ForecastDay {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<ImageRange>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day_temperature: Option<NumberInRect>,
+    #[wfrs(id = 3)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub night_temperature: Option<NumberInRect>,
+    #[wfrs(id = 4)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minus_image_index: Option<ImgId>,
+    #[wfrs(id = 5)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub degrees_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+impl Transform for Vec<ForecastDay> {
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
+        for i in 0..params.len() {
+            let param = &params[i..=i];
+            let mut day = None;
+            day.transform(param)
+                .map_err(|e| e.in_field(&format!("[{i}]")))?;
+            self.extend(day);
+        }
+        Ok(())
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        self.iter().map(|day| day.to_param()).collect()
+    }
+
+    fn field_map(key: u8) -> Option<FieldMap> {
+        Option::<ForecastDay>::field_map(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{parser::bin_parser, writer::bin_writer},
+        image::{Rgba, RgbaImage},
+        winnow::stream::Located,
+    };
+
+    #[test]
+    fn parse_and_write_gtr_bin() {
+        let bytes: Vec<u8> = [
+            &DIAL_SIGNATURE[..],
+            &[0xFF; 25],
+            &[
+                0x20, 0x00, 0x00, 0x00, // Size of biggest param
+                0x0C, 0x00, 0x00, 0x00, // Size of params info: 12
+                0x0a, 0x04, 0x08, 0x20, 0x10, 0x00, // size of params: 32, imagesCount: 0
+                0x6a, 0x04, 0x08, 0x00, 0x10, 0x20, // Forecast param info, offset 0, size 32
+                // Forecast param: two days with icon: x: 5, y: 6, imgid: 10, imgcnt: 5, minus: 3, degrees: 4
+                0x0a, 0x0e, 0x0a, 0x08, 0x08, 0x05, 0x10, 0x06, 0x18, 0x0A, 0x20, 0x05, 0x20, 0x03,
+                0x28, 0x04, // 1st day
+                0x0a, 0x0e, 0x0a, 0x08, 0x08, 0x05, 0x10, 0x06, 0x18, 0x0A, 0x20, 0x05, 0x20, 0x03,
+                0x28, 0x04, // 2nd day
+            ],
+        ]
+        .concat();
+
+        let watchface = bin_parser::<GtrParams>(Located::new(&bytes)).unwrap();
+        assert_eq!(watchface.header, DialHeader::default());
+        let parameters = watchface.parameters.as_ref().unwrap();
+        let days = &parameters.weather_forecast.as_ref().unwrap().days;
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[1].minus_image_index, Some(ImgId(3)));
+        assert_eq!(days[1].degrees_image_index, Some(ImgId(4)));
+        assert_eq!(days[1].icon.as_ref().unwrap().image_index, Some(ImgId(10)));
+
+        let watchface = Watchface {
+            source: None,
+            ..watchface
+        };
+        assert_eq!(bin_writer(&watchface).unwrap(), bytes);
+    }
+
+    #[test]
+    fn parse_uihh_as_gtr_bin() {
+        let bytes: Vec<u8> = [&SIGNATURE[..], &DEFAULT_HEADER].concat();
+        assert_eq!(
+            bin_parser::<GtrParams>(Located::new(&bytes)),
+            Err(WatchfaceError::InvalidSignature {
+                signature: [&SIGNATURE[..], &DEFAULT_HEADER[..3]].concat()
+            })
+        );
+    }
+
+    #[test]
+    fn preview_image_hand_on_round_screen() {
+        // Red hand 2x10 with center at its bottom
+        let hand = RgbaImage::from_pixel(2, 10, Rgba([0xFF, 0x00, 0x00, 0xFF]));
+        let watchface = Watchface::<GtrParams> {
+            header: DialHeader::default(),
+            parameters: Some(GtrParams {
+                analog_dial_face: Some(AnalogDialFace {
+                    minutes_image: Some(ImageHand {
+                        center: Some(Coordinates {
+                            x: 227,
+                            y: 227,
+                            ..Default::default()
+                        }),
+                        image: Some(ImageReference {
+                            x: 1,
+                            y: 9,
+                            image_index: Some(ImgId(0)),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            images: vec![Image::from(hand)],
+            source: None,
+        };
+
+        let params = PreviewParams {
+            minutes: Some(15),
+            ..Default::default()
+        };
+        let preview = watchface.render_preview(&params);
+        assert_eq!(preview.dimensions(), (454, 454));
+        // Hand points to 3 o'clock
+        assert_eq!(preview.get_pixel(233, 227).0, [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(preview.get_pixel(227, 221).0, [0x00, 0x00, 0x00, 0xFF]);
+        // Corners are out of the screen
+        assert_eq!(preview.get_pixel(0, 0).0[3], 0);
+        assert_eq!(preview.get_pixel(453, 453).0[3], 0);
+        assert_eq!(preview.get_pixel(0, 227).0[3], 0xFF);
+    }
+}
@@ -0,0 +1,277 @@
+use {
+    crate::{
+        common::*, // TODO: not use star
+        error::WatchfaceError,
+        parser::{bytes_to_usize, image_parse, parameters_parser},
+    },
+    std::fmt,
+    winnow::stream::{Located, Location, Stream as _},
+};
+
+// Labelled byte range of a file
+#[derive(Debug, PartialEq, Clone)]
+pub struct Region {
+    pub offset: usize,
+    pub length: usize,
+    pub label: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LayoutIssue {
+    Gap {
+        offset: usize,
+        length: usize,
+    },
+    Overlap {
+        offset: usize,
+        length: usize,
+        first: String,
+        second: String,
+    },
+    Trailing {
+        offset: usize,
+        length: usize,
+    },
+}
+
+// Regions are sorted by offset
+#[derive(Debug, PartialEq, Clone)]
+pub struct Layout {
+    pub regions: Vec<Region>,
+    pub issues: Vec<LayoutIssue>,
+}
+
+pub fn layout<T>(bytes: &[u8]) -> Result<Layout, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform,
+{
+    let i = &mut Located::new(bytes);
+    let mut regions = vec![];
+    let mut region = |offset: usize, length: usize, label: String| {
+        regions.push(Region {
+            offset,
+            length,
+            label,
+        })
+    };
+
+    T::Header::parse(i)?;
+    let buffer_size_start = i.location();
+    let signature_size = T::Header::SIGNATURE.len();
+    region(0, signature_size, "signature".to_string());
+    region(
+        signature_size,
+        buffer_size_start - signature_size,
+        "header".to_string(),
+    );
+
+    let raw_parameters = parameters_parser(i, T::VALUE_BITS)?;
+    region(buffer_size_start, 4, "buffer size".to_string());
+    region(buffer_size_start + 4, 4, "params info size".to_string());
+    let info_start = buffer_size_start + 8;
+    region(
+        info_start,
+        raw_parameters.sections_start - info_start,
+        "params info".to_string(),
+    );
+
+    for entry in raw_parameters.sections.entries() {
+        let name = Option::<T>::field_map(entry.key).map_or("UNMAPPED", |field| field.name);
+        for span in &entry.spans {
+            region(
+                span.offset,
+                span.length,
+                format!("section {} {name}", entry.key),
+            );
+        }
+    }
+
+    let images_info_start = i.location();
+    let images_count = raw_parameters.images_count;
+    let images_info = images_count
+        .checked_mul(4)
+        .and_then(|size| images_info_start.checked_add(size))
+        .and_then(|images_info_end| bytes.get(images_info_start..images_info_end))
+        .ok_or(WatchfaceError::UnexpectedEnd {
+            offset: bytes.len(),
+        })?;
+    region(
+        images_info_start,
+        images_info.len(),
+        "images info".to_string(),
+    );
+
+    let images_start = images_info_start + images_info.len();
+    for index in 0..images_count {
+        let image_offset = images_start + bytes_to_usize(&images_info[index * 4..index * 4 + 4]);
+        let i = &mut Located::new(bytes);
+        i.next_slice(image_offset.min(bytes.len()));
+        // Broken images are still listed to see where they are
+        match image_parse(i) {
+            Ok(image) => region(
+                image_offset,
+                i.location() - image_offset,
+                format!(
+                    "image {index}: {}x{}, {} bpp, format 0x{:x}",
+                    image.width, image.height, image.bits_per_pixel, image.pixel_format
+                ),
+            ),
+            Err(e) => region(image_offset, 0, format!("image {index}: {e}")),
+        }
+    }
+
+    regions.sort_by_key(|region| (region.offset, region.length));
+    let issues = layout_issues(&regions, bytes.len());
+    Ok(Layout { regions, issues })
+}
+
+fn layout_issues(regions: &[Region], file_size: usize) -> Vec<LayoutIssue> {
+    let mut issues = vec![];
+    let mut end = 0;
+    let mut last: Option<&Region> = None;
+    for region in regions {
+        if region.offset > end {
+            issues.push(LayoutIssue::Gap {
+                offset: end,
+                length: region.offset - end,
+            });
+        } else if region.offset < end {
+            issues.push(LayoutIssue::Overlap {
+                offset: region.offset,
+                length: end.min(region.offset + region.length) - region.offset,
+                first: last.map_or(String::new(), |last| last.label.clone()),
+                second: region.label.clone(),
+            });
+        }
+        if region.offset + region.length > end {
+            end = region.offset + region.length;
+            last = Some(region);
+        }
+    }
+    if end < file_size {
+        issues.push(LayoutIssue::Trailing {
+            offset: end,
+            length: file_size - end,
+        });
+    }
+    issues
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for region in &self.regions {
+            writeln!(
+                f,
+                "0x{:08x}..0x{:08x} {:>8} {}",
+                region.offset,
+                region.offset + region.length,
+                region.length,
+                region.label
+            )?;
+        }
+        for issue in &self.issues {
+            writeln!(f, "{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutIssue::Gap { offset, length } => {
+                write!(f, "gap of {length} bytes at 0x{offset:08x}")
+            }
+            LayoutIssue::Overlap {
+                offset,
+                length,
+                first,
+                second,
+            } => write!(
+                f,
+                "overlap of {length} bytes at 0x{offset:08x} between {first} and {second}"
+            ),
+            LayoutIssue::Trailing { offset, length } => {
+                write!(f, "trailing {length} bytes at 0x{offset:08x}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::miband::MiBandParams};
+
+    #[test]
+    fn layout_of_bin() {
+        let bytes: Vec<u8> = [
+            &SIGNATURE[..],
+            &DEFAULT_HEADER,
+            &[
+                0x09, 0x00, 0x00, 0x00, // Size of biggest param
+                0x12, 0x00, 0x00, 0x00, // Size of params info: 18
+                0x0a, 0x04, 0x08, 0x0B, 0x10, 0x02, // size of params: 11, imagesCount: 2
+                0x12, 0x04, 0x08, 0x00, 0x10, 0x09, // Background param info, offset 0, size 9
+                0x6a, 0x04, 0x08, 0x07, 0x10, 0x02, // Unknown param info, offset 7, size 2
+                0x0a, 0x07, 0x08, 0x01, 0x10, 0x82, 0x02, 0x18, 0x00, // Background param
+                0x00, 0x00, // Not used by any param
+                0x00, 0x00, 0x00, 0x00, // Offset of 1st image: 0
+                0x00, 0x00, 0x00, 0x00, // Offset of 2nd image: 0
+                // 32 bit image
+                0x42, 0x4D, 0xFF, 0xFF, 0x02, 0x00, 0x01, 0x00, 0x08, 0x00, 0x20, 0x00, 0x31, 0x21,
+                0x11, 0xFF, 0x32, 0x22, 0x12, 0x40, // Image end
+                0xAA, 0xBB, // Trailing bytes
+            ],
+        ]
+        .concat();
+
+        let layout = layout::<MiBandParams>(&bytes).unwrap();
+        let regions = layout
+            .regions
+            .iter()
+            .map(|region| (region.offset, region.length, region.label.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            regions,
+            [
+                (0, 4, "signature"),
+                (4, 75, "header"),
+                (79, 4, "buffer size"),
+                (83, 4, "params info size"),
+                (87, 18, "params info"),
+                (105, 9, "section 2 Background"),
+                (112, 2, "section 13 UNMAPPED"),
+                (116, 8, "images info"),
+                (124, 20, "image 0: 2x1, 32 bpp, format 0xffff"),
+                (124, 20, "image 1: 2x1, 32 bpp, format 0xffff"),
+            ]
+        );
+        assert_eq!(
+            layout.issues,
+            [
+                LayoutIssue::Overlap {
+                    offset: 112,
+                    length: 2,
+                    first: "section 2 Background".to_string(),
+                    second: "section 13 UNMAPPED".to_string(),
+                },
+                LayoutIssue::Gap {
+                    offset: 114,
+                    length: 2
+                },
+                LayoutIssue::Overlap {
+                    offset: 124,
+                    length: 20,
+                    first: "image 0: 2x1, 32 bpp, format 0xffff".to_string(),
+                    second: "image 1: 2x1, 32 bpp, format 0xffff".to_string(),
+                },
+                LayoutIssue::Trailing {
+                    offset: 144,
+                    length: 2
+                },
+            ]
+        );
+        assert_eq!(layout.issues[1].to_string(), "gap of 2 bytes at 0x00000072");
+    }
+}
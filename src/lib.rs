@@ -1,21 +1,45 @@
+mod animation;
+mod bipu;
 mod common;
+mod dump;
+mod error;
+mod gtr;
+mod layout;
 mod miband;
+mod miband4;
+mod miband6;
 mod parser;
 mod preview;
+mod writer;
 
 use {
-    common::*, // TODO: not use star
     parser::*, // TODO: not use star
-    winnow::{stream::Located, PResult},
+    winnow::stream::Located,
+    writer::*, // TODO: not use star
 };
 
+pub use animation::{AnimatedPreview, AnimationFrame, TimeLapse};
+pub use bipu::{BipUParams, Gts2MiniParams};
+pub use common::DialHeader;
+pub use common::FileHeader;
+pub use common::Image;
 pub use common::ImageType;
 pub use common::PreviewParams;
+pub use common::Transform;
 pub use common::Watchface;
+pub use common::WatchfaceHeader;
+pub use common::WatchfaceParams;
+pub use common::WatchfaceSource;
+pub use error::WatchfaceError;
+pub use gtr::{GtrParams, GtsParams};
+pub use layout::{Layout, LayoutIssue, Region};
 pub use miband::MiBandParams;
-use preview::Preview;
+pub use miband4::MiBand4Params;
+pub use miband6::MiBand6Params;
+pub use preview::Preview;
+pub use writer::smallest_image_format;
 
-pub fn parse_watch_face_bin<T>(bytes: &mut &[u8]) -> PResult<Watchface<T>>
+pub fn parse_watch_face_bin<T>(bytes: &mut &[u8]) -> Result<Watchface<T>, WatchfaceError>
 where
     T: WatchfaceParams,
     Option<T>: Transform + Preview,
@@ -23,9 +47,39 @@ where
     bin_parser(Located::new(bytes))
 }
 
+// Raw params of every section with offsets and typed fields they map to
+pub fn dump_watch_face_bin<T>(bytes: &[u8]) -> Result<String, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform,
+{
+    dump::dump::<T>(bytes)
+}
+
+// Labelled regions of a file with gaps, overlaps and trailing bytes between them
+pub fn watch_face_bin_layout<T>(bytes: &[u8]) -> Result<Layout, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform,
+{
+    layout::layout::<T>(bytes)
+}
+
+pub fn encode_image(image: &Image) -> Result<Vec<u8>, WatchfaceError> {
+    image_encode(image)
+}
+
+pub fn write_watch_face_bin<T>(watchface: &Watchface<T>) -> Result<Vec<u8>, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform + Preview,
+{
+    bin_writer(watchface)
+}
+
 #[cfg(test)]
 mod tests {
-    use {super::*, miband::*};
+    use {super::*, common::*, miband::*};
 
     #[test]
     fn parse_simple_bin() {
@@ -61,14 +115,19 @@ mod tests {
 
         let result: Watchface<MiBandParams> = parse_watch_face_bin(&mut &bytes[..]).unwrap();
         assert_eq!(
-            result,
             Watchface {
+                source: None,
+                ..result
+            },
+            Watchface {
+                header: WatchfaceHeader::default(),
                 parameters: Some(MiBandParams {
                     background: Some(Background {
                         image: Some(ImageReference {
                             x: 1,
                             y: 258,
                             image_index: Some(ImgId(0)),
+                            ..Default::default()
                         }),
                         ..Default::default()
                     }),
@@ -78,14 +137,17 @@ mod tests {
                                 x: 16,
                                 y: 32,
                                 image_index: Some(ImgId(0)),
-                                images_count: Some(2)
+                                images_count: Some(2),
+                                ..Default::default()
                             }),
                             ones: Some(ImageRange {
                                 x: 731,
                                 y: 12,
                                 image_index: Some(ImgId(1)),
-                                images_count: Some(7)
-                            })
+                                images_count: Some(7),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
                         }),
                         ..Default::default()
                     }),
@@ -106,8 +168,61 @@ mod tests {
                     height: 1,
                     bits_per_pixel: 32,
                     pixel_format: 0x10,
-                }]
+                }],
+                source: None,
             }
         );
     }
+
+    #[test]
+    fn parse_bin_with_32_bit_image() {
+        let bytes: Vec<u8> = [
+            &b"UIHH"[..],
+            &DEFAULT_HEADER,
+            &[
+                0x09, 0x00, 0x00, 0x00, // Size of biggest param
+                0x0C, 0x00, 0x00, 0x00, // Size of params info: 12
+                0x0a, 0x04, 0x08, 0x09, 0x10, 0x01, // size of params: 9, imagesCount: 1
+                0x12, 0x04, 0x08, 0x00, 0x10, 0x09, // Background param info, offset 0, size 9
+                0x0a, 0x07, 0x08, 0x01, 0x10, 0x82, 0x02, 0x18, 0x00, // Background param
+                0x00, 0x00, 0x00, 0x00, // Offset of 1st image: 0
+                // 32 bit image
+                0x42, 0x4D, 0xFF, 0xFF, 0x02, 0x00, 0x01, 0x00, 0x08, 0x00, 0x20, 0x00, 0x31, 0x21,
+                0x11, 0xFF, 0x32, 0x22, 0x12, 0x40,
+            ],
+        ]
+        .concat();
+
+        let result: Watchface<MiBandParams> = parse_watch_face_bin(&mut &bytes[..]).unwrap();
+        assert_eq!(
+            result.images,
+            vec![Image {
+                pixels: vec![0x11, 0x21, 0x31, 0xFF, 0x12, 0x22, 0x32, 0x40],
+                width: 2,
+                height: 1,
+                bits_per_pixel: 32,
+                pixel_format: 0xFFFF,
+            }]
+        );
+        assert_eq!(
+            result.generate_preview(None),
+            vec![ImageWithCoords {
+                x: 1,
+                y: 258,
+                image_type: ImageType::Id(ImgId(0)),
+            }]
+        );
+    }
+
+    #[test]
+    fn scenarios_are_valid() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let json = std::fs::read_to_string(&path).unwrap();
+            if let Err(e) = serde_json::from_str::<PreviewParams>(&json) {
+                panic!("{}: {e}", path.display());
+            }
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use {
     crate::common::*,
+    crate::error::WatchfaceError,
     crate::preview::{ParamType, Preview},
     derive::{PreviewDerive, TransformDerive},
     serde::{ser::SerializeSeq, Deserialize, Serialize},
@@ -8,63 +9,107 @@ use {
 
 // TODO: check that all fields from UIHH_MIBAND.json copied
 
-#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
-#[serde(rename_all = "PascalCase")]
-pub struct MiBandParams {
-    #[wfrs(id = 2)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub background: Option<Background>,
-    #[wfrs(id = 3)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub time: Option<Time>,
-    #[wfrs(id = 4)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub activity: Option<Activity>,
-    #[wfrs(id = 5)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub date: Option<Date>,
-    #[wfrs(id = 6)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub weather: Option<Weather>,
-    #[wfrs(id = 7)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub steps_progress: Option<StepsProgress>,
-    #[wfrs(id = 8)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub status: Option<Status>,
-    #[wfrs(id = 9)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub battery: Option<Battery>,
-    #[wfrs(id = 10)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub analog_dial_face: Option<AnalogDialFace>,
-    #[wfrs(id = 11)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub other: Option<Other>,
-    #[wfrs(id = 12)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub heart_progress: Option<HeartProgress>,
-    #[wfrs(id = 14)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub week_days_icons: Option<WeekDaysIcons>,
-    #[wfrs(id = 15)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub calories_progress: Option<CaloriesProgress>,
-    #[wfrs(id = 18)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub alarm: Option<Alarm>,
-    #[wfrs(id = 20)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub status2: Option<Status>,
-    #[wfrs(id = 21)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub unknown: Option<UnknownStruct>,
-    #[wfrs(id = 22)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub lunar_date: Option<LunarDate>,
+// Declares params of a device with sections of Mi Band 5, only sections which differ between
+// devices are listed. They are placed among shared sections by id, as sections are drawn in
+// the order of fields. Sections which are not described yet are kept in extra params.
+// Types of sections should be imported where params are declared
+macro_rules! mi_band_params {
+    (
+        pub struct $name:ident { $($sections:tt)* }
+        impl WatchfaceParams { $($device:tt)* }
+    ) => {
+        mi_band_params!(@sort $name [] [] [] [] { $($device)* } $($sections)*);
+    };
+    (@sort $name:ident [$($a:tt)*] $b:tt $c:tt $d:tt $device:tt
+        10 => $field:ident: $section:ident, $($rest:tt)*) => {
+        mi_band_params!(@sort $name [$($a)* 10 => $field: $section,] $b $c $d $device $($rest)*);
+    };
+    (@sort $name:ident $a:tt [$($b:tt)*] $c:tt $d:tt $device:tt
+        13 => $field:ident: $section:ident, $($rest:tt)*) => {
+        mi_band_params!(@sort $name $a [$($b)* 13 => $field: $section,] $c $d $device $($rest)*);
+    };
+    (@sort $name:ident $a:tt [$($b:tt)*] $c:tt $d:tt $device:tt
+        14 => $field:ident: $section:ident, $($rest:tt)*) => {
+        mi_band_params!(@sort $name $a [$($b)* 14 => $field: $section,] $c $d $device $($rest)*);
+    };
+    (@sort $name:ident $a:tt $b:tt [$($c:tt)*] $d:tt $device:tt
+        16 => $field:ident: $section:ident, $($rest:tt)*) => {
+        mi_band_params!(@sort $name $a $b [$($c)* 16 => $field: $section,] $d $device $($rest)*);
+    };
+    (@sort $name:ident $a:tt $b:tt $c:tt [$($d:tt)*] $device:tt
+        $id:tt => $field:ident: $section:ident, $($rest:tt)*) => {
+        mi_band_params!(@sort $name $a $b $c [$($d)* $id => $field: $section,] $device $($rest)*);
+    };
+    (@sort $name:ident [$($a:tt)*] [$($b:tt)*] [$($c:tt)*] [$($d:tt)*] $device:tt) => {
+        mi_band_params!(@struct $name $device
+            2 => background: Background,
+            3 => time: Time,
+            4 => activity: Activity,
+            5 => date: Date,
+            6 => weather: Weather,
+            7 => steps_progress: StepsProgress,
+            8 => status: Status,
+            9 => battery: Battery,
+            $($a)*
+            11 => other: Other,
+            12 => heart_progress: HeartProgress,
+            $($b)*
+            15 => calories_progress: CaloriesProgress,
+            $($c)*
+            18 => alarm: Alarm,
+            $($d)*
+        );
+    };
+    (@struct $name:ident { $($device:tt)* } $($id:tt => $field:ident: $section:ident,)*) => {
+        #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
+        #[serde(rename_all = "PascalCase")]
+        pub struct $name {
+            $(
+                #[wfrs(id = $id)]
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub $field: Option<$section>,
+            )*
+            #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+            pub extra: ExtraParams,
+        }
+
+        impl WatchfaceParams for $name {
+            $($device)*
+
+            fn background_color(&self) -> Option<&Color> {
+                self.background.as_ref()?.color.as_ref()
+            }
+
+            fn animations(&self) -> &[Animation] {
+                self.other
+                    .as_ref()
+                    .map_or(&[], |other| &other.animation.0[..])
+            }
+        }
+    };
 }
 
-impl WatchfaceParams for MiBandParams {}
+pub(crate) use mi_band_params;
+
+mi_band_params! {
+    pub struct MiBandParams {
+        10 => analog_dial_face: AnalogDialFace,
+        14 => week_days_icons: WeekDaysIcons,
+        20 => status2: Status,
+        21 => unknown: UnknownStruct,
+        22 => lunar_date: LunarDate,
+    }
+    impl WatchfaceParams {
+        type Header = WatchfaceHeader;
+
+        const SCREEN_WIDTH: u32 = 126;
+        const SCREEN_HEIGHT: u32 = 294;
+
+        fn default_header() -> Option<WatchfaceHeader> {
+            Some(WatchfaceHeader::default())
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
 #[serde(rename_all = "PascalCase")]
@@ -84,6 +129,8 @@ pub struct Background {
     #[wfrs(id = 5)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "PreviewCN2")]
     pub preview_cn2: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -125,6 +172,8 @@ pub struct Time {
     #[wfrs(id = 13)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sunrise_time_no_data_image: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -136,6 +185,8 @@ pub struct TimeNumbers {
     #[wfrs(id = 2, params = ["U32", "param % 10"])]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ones: Option<ImageRange>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -158,6 +209,8 @@ pub struct Activity {
     pub pai: Option<PAI>,
     #[wfrs(id = 7)]
     pub unknown_v7: i32,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -172,6 +225,8 @@ pub struct Steps {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -183,6 +238,8 @@ pub struct Calories {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -200,6 +257,8 @@ pub struct Pulse {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -217,14 +276,19 @@ pub struct Distance {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub miles_suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
 #[serde(rename_all = "PascalCase")]
+#[allow(clippy::upper_case_acronyms)]
 pub struct PAI {
     #[wfrs(id = 1)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub number: Option<NumberInRect>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -245,6 +309,8 @@ pub struct Date {
     #[wfrs(id = 6)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "CN2WeekDays")]
     pub cn2_week_days: Option<ImageRange>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -265,6 +331,8 @@ pub struct MonthAndDayAndYear {
     #[wfrs(id = 5)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub two_digits_day: Option<bool>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -282,6 +350,8 @@ pub struct Separate {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub day: Option<NumberInRect>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -293,6 +363,8 @@ pub struct OneLine {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub delimiter_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -320,6 +392,8 @@ pub struct DayAmPm {
     #[wfrs(id = 8)]
     #[serde(rename = "Y_EN", skip_serializing_if = "Option::is_none")]
     pub y_en: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -334,6 +408,8 @@ pub struct Status {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bluetooth: Option<StatusImage>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -357,6 +433,8 @@ pub struct Weather {
     #[wfrs(id = 6)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "UVIndex")]
     pub uv_index: Option<UVIndex>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -371,6 +449,8 @@ pub struct Icon {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub position2: Option<Coordinates>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -382,6 +462,8 @@ pub struct Temperature {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub today: Option<Today>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -393,6 +475,8 @@ pub struct Today {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub one_line: Option<TodayOneLine>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -404,6 +488,8 @@ pub struct TemperatureSeparate {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub night: Option<TemperatureType>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -424,6 +510,8 @@ pub struct TodayOneLine {
     #[wfrs(id = 5)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -435,6 +523,8 @@ pub struct AirQuality {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub icon: Option<ImageRange>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -449,6 +539,8 @@ pub struct Humidity {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_pos_suffix: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -478,6 +570,8 @@ pub struct Wind {
     #[wfrs(id = 7)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "ImagePosSuffixCN2")]
     pub image_pos_suffix_cn2: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -492,6 +586,8 @@ pub struct UVIndex {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "UVCN2")]
     pub uvcn2: Option<ImageRange>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -503,6 +599,8 @@ pub struct UV {
     #[wfrs(id = 2)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "UVCN")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -520,6 +618,8 @@ pub struct StepsProgress {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub circle_scale: Option<CircleScale>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -534,6 +634,8 @@ pub struct Battery {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub linear: Option<Linear>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -548,6 +650,8 @@ pub struct BatteryText {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix_image_index: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -558,6 +662,8 @@ pub struct Linear {
     pub start_image_index: Option<ImgId>,
     #[wfrs(id = 2)]
     pub segments: Vec<Coordinates>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -572,9 +678,11 @@ pub struct AnalogDialFace {
     #[wfrs(id = 3, params = ["U32", "seconds", "F32", "60."])]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seconds: Option<VectorShape>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
-#[derive(Debug, PartialEq, Default, Deserialize)]
+#[derive(Debug, PartialEq, Default)]
 pub struct Animations(pub Vec<Animation>);
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -582,6 +690,8 @@ pub struct Animations(pub Vec<Animation>);
 pub struct Other {
     #[wfrs(id = 1)]
     pub animation: Animations,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -599,16 +709,32 @@ pub struct Animation {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unknown_v4: Option<u32>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 impl Transform for Animations {
-    fn transform(&mut self, params: &[Param]) {
+    fn transform(&mut self, params: &[Param]) -> Result<(), WatchfaceError> {
         for i in 0..params.len() {
             let param = &params[i..=i]; // heh
             let mut animations = None;
-            animations.transform(param);
-            self.0.push(animations.unwrap());
+            animations
+                .transform(param)
+                .map_err(|e| e.in_field(&format!("[{i}]")))?;
+            self.0.extend(animations);
         }
+        Ok(())
+    }
+
+    fn untransform(&self) -> Vec<Param> {
+        self.0
+            .iter()
+            .map(|animation| animation.to_param())
+            .collect()
+    }
+
+    fn field_map(key: u8) -> Option<FieldMap> {
+        Option::<Animation>::field_map(key)
     }
 }
 
@@ -629,6 +755,26 @@ impl Serialize for Animations {
     }
 }
 
+// Single animation is written without list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnimationsValue {
+    Many(Vec<Animation>),
+    One(Animation),
+}
+
+impl<'de> Deserialize<'de> for Animations {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(match AnimationsValue::deserialize(deserializer)? {
+            AnimationsValue::Many(animations) => Animations(animations),
+            AnimationsValue::One(animation) => Animations(vec![animation]),
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
 #[serde(rename_all = "PascalCase")]
 pub struct HeartProgress {
@@ -641,6 +787,8 @@ pub struct HeartProgress {
     #[wfrs(id = 4)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub circle_scale: Option<CircleScale>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -670,6 +818,8 @@ pub struct CircleScale {
     #[wfrs(id = 8)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub color: Option<Color>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -696,6 +846,8 @@ pub struct WeekDaysIcons {
     #[wfrs(id = 7)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sunday: Option<ImageReference>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -710,6 +862,8 @@ pub struct CaloriesProgress {
     #[wfrs(id = 3)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub linear: Option<Linear>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
@@ -734,6 +888,8 @@ pub struct Alarm {
     unknown_v6: i32,
     #[wfrs(id = 7)]
     unknown_v7: i32,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -760,6 +916,8 @@ pub struct UnknownStruct {
     #[wfrs(id = 7)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unknown_7: Option<ImgId>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
@@ -789,4 +947,359 @@ pub struct LunarDate {
     #[wfrs(id = 10)]
     #[serde(skip_serializing_if = "Option::is_none", rename = "DayCN2")]
     pub day_cn2: Option<NumberInRect>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn assert_json_round_trip(json: serde_json::Value) {
+        let params: MiBandParams = serde_json::from_value(json.clone()).unwrap();
+        let text = serde_json::to_string(&params).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            json
+        );
+        assert_eq!(serde_json::from_str::<MiBandParams>(&text).unwrap(), params);
+    }
+
+    fn reference(id: u32) -> serde_json::Value {
+        json!({"X": 1, "Y": -2, "ImageIndex": id})
+    }
+
+    fn range(id: u32) -> serde_json::Value {
+        json!({"X": -3, "Y": 4, "ImageIndex": id, "ImagesCount": 10})
+    }
+
+    fn number(id: u32) -> serde_json::Value {
+        json!({
+            "TopLeftX": 1,
+            "TopLeftY": 2,
+            "BottomRightX": 30,
+            "BottomRightY": 40,
+            "Alignment": "BottomRight",
+            "SpacingX": -1,
+            "SpacingY": 0,
+            "ImageIndex": id,
+            "ImagesCount": 10
+        })
+    }
+
+    fn linear() -> serde_json::Value {
+        json!({"StartImageIndex": 7, "Segments": [{"X": 1, "Y": 2}, {"X": 3, "Y": 4}]})
+    }
+
+    #[test]
+    fn background_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Background": {
+                "Image": reference(0),
+                "Color": "0xFF8000",
+                "PreviewEN": reference(1),
+                "PreviewCN": reference(2),
+                "PreviewCN2": reference(3)
+            }
+        }));
+    }
+
+    #[test]
+    fn time_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Time": {
+                "Hours": {"Tens": range(0), "Ones": range(10)},
+                "Minutes": {"Tens": range(20), "Ones": range(30)},
+                "Seconds": {"Tens": range(40), "Ones": range(50)},
+                "DelimiterImage": reference(60),
+                "TimeDelimiterImage": reference(61),
+                "SunsetTimeNumber": number(62),
+                "SunsetTimeDelimiterImageIndex": 72,
+                "SunriseTimeNumber": number(73),
+                "SunriseTimeDelimiterImageIndex": 83,
+                "DrawingOrder": true,
+                "SunsetTimeNoDataImage": reference(84),
+                "SunriseTimeNoDataImage": reference(85)
+            }
+        }));
+    }
+
+    #[test]
+    fn activity_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Activity": {
+                "Steps": {"Number": number(0), "PrefixImageIndex": 10, "SuffixImageIndex": 11},
+                "Calories": {"Number": number(0), "SuffixImageIndex": 12},
+                "Pulse": {
+                    "Number": number(0),
+                    "PrefixImageIndex": 13,
+                    "NoDataImageIndex": 14,
+                    "SuffixImageIndex": 15
+                },
+                "Distance": {
+                    "Number": number(0),
+                    "KmSuffixImageIndex": 16,
+                    "DecimalPointImageIndex": 17,
+                    "MilesSuffixImageIndex": 18
+                },
+                "PAI": {"Number": number(0)},
+                "UnknownV7": 3
+            }
+        }));
+    }
+
+    #[test]
+    fn date_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Date": {
+                "MonthAndDayAndYear": {
+                    "Separate": {
+                        "Month": number(0),
+                        "MonthsEN": range(10),
+                        "MonthsCN": range(22),
+                        "Day": number(34)
+                    },
+                    "OneLine": {"Number": number(0), "DelimiterImageIndex": 10},
+                    "OneLineWithYear": {"Number": number(0), "DelimiterImageIndex": 11},
+                    "TwoDigitsMonth": true,
+                    "TwoDigitsDay": false
+                },
+                "DayAmPm": {
+                    "X": 5,
+                    "Y": 6,
+                    "ImageIndexAMCN": 1,
+                    "ImageIndexPMCN": 2,
+                    "ImageIndexAMEN": 3,
+                    "ImageIndexPMEN": 4,
+                    "X_EN": 7,
+                    "Y_EN": 8
+                },
+                "ENWeekDays": range(50),
+                "CNWeekDays": range(57),
+                "CN2WeekDays": range(64)
+            }
+        }));
+    }
+
+    #[test]
+    fn weather_json_round_trip() {
+        let temperature =
+            json!({"Number": number(0), "MinusImageIndex": 10, "SuffixImageIndex": 11});
+        assert_json_round_trip(json!({
+            "Weather": {
+                "Icon": {
+                    "CustomIcon": range(20),
+                    "Position1": {"X": 1, "Y": 2},
+                    "Position2": {"X": 3, "Y": 4}
+                },
+                "Temperature": {
+                    "Current": temperature,
+                    "Today": {
+                        "Separate": {"Day": temperature, "Night": temperature},
+                        "OneLine": {
+                            "Number": number(0),
+                            "MinusImageIndex": 10,
+                            "DelimiterImageIndex": 12,
+                            "AppendSuffixToAll": true,
+                            "SuffixImageIndex": 11
+                        }
+                    }
+                },
+                "AirQuality": {"Index": number(0), "Icon": range(30)},
+                "Humidity": {
+                    "Number": number(0),
+                    "SuffixImageIndex": 13,
+                    "ImagePosSuffix": reference(13)
+                },
+                "Wind": {
+                    "Number": number(0),
+                    "SuffixImageIndexEN": 14,
+                    "SuffixImageIndexCN": 15,
+                    "SuffixImageIndexCN2": 16,
+                    "ImagePosSuffixEN": reference(14),
+                    "ImagePosSuffixCN": reference(15),
+                    "ImagePosSuffixCN2": reference(16)
+                },
+                "UVIndex": {
+                    "UV": {"Number": number(0), "UVCN": 17},
+                    "UVCN": range(40),
+                    "UVCN2": range(50)
+                }
+            }
+        }));
+    }
+
+    #[test]
+    fn steps_progress_json_round_trip() {
+        assert_json_round_trip(json!({
+            "StepsProgress": {
+                "GoalImage": reference(0),
+                "LineScale": range(1),
+                "Linear": linear(),
+                "CircleScale": {
+                    "CenterX": 63,
+                    "CenterY": 147,
+                    "RadiusX": 50,
+                    "RadiusY": 50,
+                    "StartAngle": 0,
+                    "EndAngle": 360,
+                    "Width": 4,
+                    "Color": "0x12345678"
+                }
+            }
+        }));
+    }
+
+    #[test]
+    fn status_json_round_trip() {
+        let status = json!({
+            "DoNotDisturb": {
+                "Coordinates": {"X": 1, "Y": 2, "Alignment": "Unknown", "Unknown4": 4, "Unknown5": 5},
+                "OnImageIndex": 1,
+                "OffImageIndex": 2
+            },
+            "Lock": {"Coordinates": {"X": 3, "Y": 4, "Alignment": "TopLeft"}, "OnImageIndex": 3},
+            "Bluetooth": {"Coordinates": {"X": 5, "Y": 6}, "OffImageIndex": 4}
+        });
+        assert_json_round_trip(json!({"Status": status, "Status2": status}));
+    }
+
+    #[test]
+    fn battery_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Battery": {
+                "BatteryText": {
+                    "Number": number(0),
+                    "PrefixImageIndex": 10,
+                    "SuffixImageIndex": 11
+                },
+                "BatteryIcon": range(12),
+                "Linear": linear()
+            }
+        }));
+    }
+
+    #[test]
+    fn analog_dial_face_json_round_trip() {
+        let shape = json!({
+            "OnlyBorder": false,
+            "Color": "0xFFFFFF",
+            "Center": {"X": 63, "Y": 147},
+            "Shape": [{"X": -5, "Y": 0}, {"X": 0, "Y": 60}, {"X": 5, "Y": 0}],
+            "CenterImage": reference(0)
+        });
+        assert_json_round_trip(json!({
+            "AnalogDialFace": {"Hours": shape, "Minutes": shape, "Seconds": shape}
+        }));
+    }
+
+    #[test]
+    fn other_json_round_trip() {
+        let animation = json!({
+            "AnimationImages": range(0),
+            "Speed": 100,
+            "RepeatCount": 255,
+            "UnknownV4": 1
+        });
+        assert_json_round_trip(json!({"Other": {"Animation": animation}}));
+        assert_json_round_trip(json!({"Other": {"Animation": [animation, animation]}}));
+        assert_json_round_trip(json!({"Other": {"Animation": []}}));
+    }
+
+    #[test]
+    fn heart_progress_json_round_trip() {
+        assert_json_round_trip(json!({
+            "HeartProgress": {
+                "LineScale": range(0),
+                "Linear": linear(),
+                "CircleScale": {"CenterX": 10, "CenterY": 20, "Color": "0xFF0000"}
+            }
+        }));
+    }
+
+    #[test]
+    fn week_days_icons_json_round_trip() {
+        assert_json_round_trip(json!({
+            "WeekDaysIcons": {
+                "Monday": reference(0),
+                "Tuesday": reference(1),
+                "Wednesday": reference(2),
+                "Thursday": reference(3),
+                "Friday": reference(4),
+                "Saturday": reference(5),
+                "Sunday": reference(6)
+            }
+        }));
+    }
+
+    #[test]
+    fn calories_progress_json_round_trip() {
+        assert_json_round_trip(json!({
+            "CaloriesProgress": {
+                "GoalImage": reference(0),
+                "LineScale": range(1),
+                "Linear": linear()
+            }
+        }));
+    }
+
+    #[test]
+    fn alarm_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Alarm": {
+                "Number": number(0),
+                "DelimiterImageIndex": 10,
+                "OnImage": reference(11),
+                "OffImage": reference(12),
+                "NoDataImage": reference(13),
+                "UnknownV6": 6,
+                "UnknownV7": 7
+            }
+        }));
+    }
+
+    #[test]
+    fn unknown_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Unknown": {
+                "Unknown1": number(0),
+                "Unknown2": number(10),
+                "Unknown3": 20,
+                "Unknown4": 21,
+                "Unknown5": 22,
+                "Unknown6": 23,
+                "Unknown7": 24
+            }
+        }));
+    }
+
+    #[test]
+    fn lunar_date_json_round_trip() {
+        assert_json_round_trip(json!({
+            "LunarDate": {
+                "Month": range(0),
+                "Day": number(12),
+                "DayOf0X": 22,
+                "DayOf2X": 23,
+                "DayOf10": 24,
+                "DayOf20": 25,
+                "DayOf30": 26,
+                "DayCN2": number(27)
+            }
+        }));
+    }
+
+    #[test]
+    fn extra_json_round_trip() {
+        assert_json_round_trip(json!({
+            "Background": {
+                "Image": reference(0),
+                "Extra": {"9": [5]}
+            },
+            "Extra": {
+                "13": [1, -2, 2.5],
+                "16": [{"1": [{"2": [3]}], "4": [0.5]}]
+            }
+        }));
+    }
 }
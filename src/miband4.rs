@@ -0,0 +1,236 @@
+use {
+    crate::common::*,
+    crate::error::WatchfaceError,
+    crate::miband::{Background, Distance, OneLine, Steps, TimeNumbers},
+    crate::preview::{ParamType, Preview},
+    derive::{PreviewDerive, TransformDerive},
+    serde::{Deserialize, Serialize},
+    std::fmt::Debug,
+};
+
+// Mi Band 4 faces have HMDIAL header like Amazfit GTR and fewer elements than Mi Band 5.
+// Ids of sections follow Mi Band 5 ones and are not checked against real faces yet,
+// sections which are not described yet are kept in extra params
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct MiBand4Params {
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<Background>,
+    #[wfrs(id = 3)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<Time>,
+    #[wfrs(id = 4)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<Activity>,
+    #[wfrs(id = 5)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<Date>,
+    #[wfrs(id = 8)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    #[wfrs(id = 9)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery: Option<Battery>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+impl WatchfaceParams for MiBand4Params {
+    type Header = DialHeader;
+
+    const SCREEN_WIDTH: u32 = 120;
+    const SCREEN_HEIGHT: u32 = 240;
+
+    fn background_color(&self) -> Option<&Color> {
+        self.background.as_ref()?.color.as_ref()
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive, PreviewDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Time {
+    #[wfrs(id = 1, params = ["U32", "hours"])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hours: Option<TimeNumbers>,
+    #[wfrs(id = 2, params = ["U32", "minutes"])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minutes: Option<TimeNumbers>,
+    #[wfrs(id = 3, params = ["U32", "seconds"])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds: Option<TimeNumbers>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Activity {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calories: Option<NumberInRect>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pulse: Option<NumberInRect>,
+    #[wfrs(id = 3)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Steps>,
+    #[wfrs(id = 5)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<Distance>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Date {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month_and_day: Option<MonthAndDay>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub week_day: Option<ImageRange>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct MonthAndDay {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separate: Option<Separate>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub one_line: Option<OneLine>,
+    #[wfrs(id = 3)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_digits_month: Option<bool>,
+    #[wfrs(id = 4)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_digits_day: Option<bool>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Separate {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month: Option<NumberInRect>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<NumberInRect>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Status {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bluetooth: Option<StatusImage>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alarm: Option<StatusImage>,
+    #[wfrs(id = 3)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock: Option<StatusImage>,
+    #[wfrs(id = 4)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub do_not_disturb: Option<StatusImage>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize, TransformDerive)]
+#[serde(rename_all = "PascalCase")]
+pub struct Battery {
+    #[wfrs(id = 1)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<NumberInRect>,
+    #[wfrs(id = 2)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<ImageRange>,
+    #[serde(default, skip_serializing_if = "ExtraParams::is_empty")]
+    pub extra: ExtraParams,
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::parser::bin_parser, winnow::stream::Located};
+
+    #[test]
+    fn parse_and_preview_miband4_bin() {
+        let bytes: Vec<u8> = [
+            &DIAL_SIGNATURE[..],
+            &[0xFF; 25],
+            &[
+                0x0A, 0x00, 0x00, 0x00, // Size of biggest param
+                0x12, 0x00, 0x00, 0x00, // Size of params info: 18
+                0x0a, 0x04, 0x08, 0x14, 0x10, 0x00, // size of params: 20, imagesCount: 0
+                0x42, 0x04, 0x08, 0x00, 0x10, 0x0A, // Status param info, offset 0, size 10
+                0x4a, 0x04, 0x08, 0x0A, 0x10, 0x0A, // Battery param info, offset 10, size 10
+                // Status param: alarm: x: 3, y: 4, on: 7
+                0x12, 0x08, 0x0a, 0x04, 0x08, 0x03, 0x10, 0x04, 0x10, 0x07, // Status param
+                // Battery param: icon: x: 5, y: 6, imgid: 10, imgcnt: 5
+                0x12, 0x08, 0x08, 0x05, 0x10, 0x06, 0x18, 0x0A, 0x20, 0x05, // Battery param
+            ],
+        ]
+        .concat();
+
+        let watchface = bin_parser::<MiBand4Params>(Located::new(&bytes)).unwrap();
+        let parameters = watchface.parameters.as_ref().unwrap();
+        let alarm = parameters.status.as_ref().unwrap().alarm.as_ref().unwrap();
+        let coordinates = alarm.coordinates.as_ref().unwrap();
+        assert_eq!((coordinates.x, coordinates.y), (3, 4));
+        assert_eq!(alarm.on_image_index, Some(ImgId(7)));
+
+        let params = PreviewParams {
+            battery: Some(50),
+            alarm_on: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            watchface.generate_preview(Some(params)),
+            vec![
+                ImageWithCoords {
+                    x: 3,
+                    y: 4,
+                    image_type: ImageType::Id(ImgId(7)),
+                },
+                ImageWithCoords {
+                    x: 5,
+                    y: 6,
+                    image_type: ImageType::Id(ImgId(12)),
+                },
+            ]
+        );
+        assert_eq!(
+            watchface
+                .render_preview(&PreviewParams::default())
+                .dimensions(),
+            (120, 240)
+        );
+    }
+
+    #[test]
+    fn skip_battery_icon_without_images() {
+        let battery = Some(Battery {
+            icon: Some(ImageRange {
+                image_index: Some(ImgId(0)),
+                images_count: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let params = Some(PreviewParams {
+            battery: Some(50),
+            ..Default::default()
+        });
+        assert!(battery.get_images(&params, &[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use {
+    crate::common::*,
+    crate::error::WatchfaceError,
+    crate::miband::*,
+    crate::preview::{ParamType, Preview},
+    derive::{PreviewDerive, TransformDerive},
+    serde::{Deserialize, Serialize},
+    std::fmt::Debug,
+};
+
+// Mi Band 6 uses the same sections as Mi Band 5 on a bigger screen. Its faces can have
+// section 16 which Mi Band 5 faces do not have, it is kept in extra params until its fields
+// are known from real faces
+mi_band_params! {
+    pub struct MiBand6Params {
+        10 => analog_dial_face: AnalogDialFace,
+        14 => week_days_icons: WeekDaysIcons,
+        20 => status2: Status,
+        21 => unknown: UnknownStruct,
+        22 => lunar_date: LunarDate,
+    }
+    impl WatchfaceParams {
+        // Default header is not known, so the header of an extracted face is needed to pack one
+        type Header = WatchfaceHeader;
+
+        const SCREEN_WIDTH: u32 = 152;
+        const SCREEN_HEIGHT: u32 = 486;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{parser::bin_parser, writer::header_writer},
+        winnow::stream::Located,
+    };
+
+    #[test]
+    fn parse_and_preview_miband6_bin() {
+        // Not the Mi Band 5 header, as faces are not tied to it
+        let header = WatchfaceHeader {
+            device_id: 0x42,
+            watchface_id: 20000,
+            ..Default::default()
+        };
+        let bytes: Vec<u8> = [
+            &header_writer(&header)[..],
+            &[
+                0x09, 0x00, 0x00, 0x00, // Size of biggest param
+                0x13, 0x00, 0x00, 0x00, // Size of params info: 19
+                0x0a, 0x04, 0x08, 0x0B, 0x10, 0x00, // size of params: 11, imagesCount: 0
+                0x12, 0x04, 0x08, 0x00, 0x10, 0x09, // Background param info, offset 0, size 9
+                0x82, 0x01, 0x04, 0x08, 0x09, 0x10, 0x02, // Param 16 info, offset 9, size 2
+                // Background param: x: 0, y: 0, color: red
+                0x0a, 0x04, 0x08, 0x00, 0x10, 0x00, 0x10, 0xFF, 0x01, // Background param
+                0x08, 0x03, // Param 16 which fields are not described yet
+            ],
+        ]
+        .concat();
+
+        let watchface = bin_parser::<MiBand6Params>(Located::new(&bytes)).unwrap();
+        assert_eq!(
+            (watchface.header.device_id, watchface.header.watchface_id),
+            (0x42, 20000)
+        );
+        assert_eq!(MiBand6Params::default_header(), None);
+        let parameters = watchface.parameters.as_ref().unwrap();
+        assert_eq!(
+            parameters.extra.0,
+            Params::from([(
+                16,
+                vec![Param::Child(Params::from([(1, vec![Param::Number(3)])]))]
+            )])
+        );
+
+        let preview = watchface.render_preview(&PreviewParams::default());
+        assert_eq!(preview.dimensions(), (152, 486));
+        assert_eq!(preview.get_pixel(151, 485).0, [0xFF, 0x00, 0x00, 0xFF]);
+    }
+}
@@ -1,9 +1,6 @@
 use {
-    crate::{common::*, preview::Preview}, // TODO: not use star
-    std::{
-        collections::{hash_map::Entry, HashMap},
-        mem::size_of,
-    },
+    crate::{common::*, error::WatchfaceError, preview::Preview}, // TODO: not use star
+    std::mem::size_of,
     winnow::{
         binary::{be_u16, le_f32, le_u16, le_u32, u8},
         stream::{Located, Location, Stream as _},
@@ -13,7 +10,8 @@ use {
 
 pub type Stream<'i> = Located<&'i [u8]>;
 
-pub fn variable_width_value_parser(i: &mut Stream) -> PResult<(i64, usize)> {
+// Values are sign extended from `bits`, so 32 bit formats can store negative values in 5 bytes
+pub fn variable_width_value_parser(i: &mut Stream, bits: u32) -> PResult<(i64, usize)> {
     let mut value = 0i64;
     let bytes = token::take_till(0..10, |b| b & 0x80 != 0x80).parse_next(i)?;
     let last = u8.parse_next(i)?;
@@ -22,13 +20,20 @@ pub fn variable_width_value_parser(i: &mut Stream) -> PResult<(i64, usize)> {
         value |= (b as i64 & 0x7f) << (i * 7);
         i += 1;
     }
+    if bits < 64 {
+        value = (value << (64 - bits)) >> (64 - bits);
+    }
 
     Ok((value, i))
 }
 
-pub fn write_variable_width_value(value: i64) -> Vec<u8> {
+// Negative values are written as `bits` wide two's complement
+pub fn write_variable_width_value(value: i64, bits: u32) -> Vec<u8> {
     let mut result = vec![];
     let mut value_big_int = value as u64;
+    if bits < 64 {
+        value_big_int &= (1 << bits) - 1;
+    }
 
     for _ in 0..10 {
         // Read lower 7 bits of value
@@ -48,29 +53,28 @@ pub fn write_variable_width_value(value: i64) -> Vec<u8> {
     result
 }
 
-pub fn image_parse(i: &mut Stream) -> PResult<Image> {
-    let signature = le_u16.parse_next(i)?;
+pub fn image_parse(i: &mut Stream) -> Result<Image, WatchfaceError> {
+    let offset = i.location();
+    let signature = le_u16.parse_next(i).at(i)?;
     if signature != 0x4D42 {
-        panic!("Invalid image signature: {}", signature);
+        return Err(WatchfaceError::InvalidImageSignature { offset, signature });
     }
 
     // read header
-    let pixel_format = le_u16.parse_next(i)?;
+    let pixel_format = le_u16.parse_next(i).at(i)?;
 
     if pixel_format == 0x65 {
-        todo!();
-        // return parseCompressedImage(dataBuffer)
+        return compressed_image_parse(i, offset);
     } else if pixel_format == 0xFFFF {
-        todo!();
-        // return parse32BitImage(dataBuffer)
+        return bit32_image_parse(i, offset);
     }
 
-    let width = le_u16.parse_next(i)?;
-    let height = le_u16.parse_next(i)?;
-    let row_size = le_u16.parse_next(i)?;
-    let bits_per_pixel = le_u16.parse_next(i)?;
-    let palette_colors_count = le_u16.parse_next(i)?;
-    let transparent_palette_color = le_u16.parse_next(i)?;
+    let width = le_u16.parse_next(i).at(i)?;
+    let height = le_u16.parse_next(i).at(i)?;
+    let row_size = le_u16.parse_next(i).at(i)?;
+    let bits_per_pixel = le_u16.parse_next(i).at(i)?;
+    let palette_colors_count = le_u16.parse_next(i).at(i)?;
+    let transparent_palette_color = le_u16.parse_next(i).at(i)?;
 
     if !([16, 24, 32].contains(&bits_per_pixel)
         && palette_colors_count == 0
@@ -79,11 +83,21 @@ pub fn image_parse(i: &mut Stream) -> PResult<Image> {
             && palette_colors_count > 0
             && pixel_format == 0x64)
     {
-        panic!("Unsuported pixel format/color depth/Palette (should add support) {pixel_format} {bits_per_pixel} {palette_colors_count}")
+        return Err(WatchfaceError::UnsupportedPixelFormat {
+            offset,
+            pixel_format,
+            bits_per_pixel,
+            palette_colors_count,
+        });
     }
 
-    if ((bits_per_pixel * width) as f32 / 8.).ceil() as u16 != row_size {
-        panic!("Row size is not as expected (Padding ?)")
+    let expected = ((bits_per_pixel as u32 * width as u32) as f32 / 8.).ceil() as u16;
+    if expected != row_size {
+        return Err(WatchfaceError::WrongRowSize {
+            offset,
+            row_size,
+            expected,
+        });
     }
 
     let mut palette = vec![];
@@ -92,9 +106,9 @@ pub fn image_parse(i: &mut Stream) -> PResult<Image> {
         // Read palette
         for color_number in 0..palette_colors_count {
             let color = (
-                u8.parse_next(i)?,
-                u8.parse_next(i)?,
-                u8.parse_next(i)?,
+                u8.parse_next(i).at(i)?,
+                u8.parse_next(i).at(i)?,
+                u8.parse_next(i).at(i)?,
                 if (transparent_palette_color != 0)
                     && (color_number == transparent_palette_color - 1)
                 {
@@ -103,16 +117,18 @@ pub fn image_parse(i: &mut Stream) -> PResult<Image> {
                     0x00
                 },
             );
-            u8.parse_next(i)?;
+            u8.parse_next(i).at(i)?;
             palette.push(color);
         }
     }
 
-    let mut prev_byte = -1;
+    let mut prev_byte;
     let mut val = 0;
     // Read pixel data
     let mut pixels = vec![0; 4usize * width as usize * height as usize];
     for y in 0..height {
+        // every row starts from a new byte
+        prev_byte = -1;
         for x in 0..width {
             // read pixel color info
             let red;
@@ -127,7 +143,7 @@ pub fn image_parse(i: &mut Stream) -> PResult<Image> {
                     let new_byte = ((x / pixels_per_byte) as f32).floor() as i32;
                     let byte = if new_byte != prev_byte {
                         prev_byte = new_byte;
-                        val = u8.parse_next(i)?;
+                        val = u8.parse_next(i).at(i)?;
                         val
                     } else {
                         val
@@ -136,26 +152,33 @@ pub fn image_parse(i: &mut Stream) -> PResult<Image> {
                     let bit_position = 8 - ((x % pixels_per_byte) + 1) * bits_per_pixel;
                     color_id = (byte >> bit_position) & bit_mask;
                 } else {
-                    color_id = u8.parse_next(i)?;
+                    color_id = u8.parse_next(i).at(i)?;
                 }
-                (red, green, blue, alpha) = palette[color_id as usize];
+                (red, green, blue, alpha) =
+                    *palette
+                        .get(color_id as usize)
+                        .ok_or(WatchfaceError::WrongColorId {
+                            offset,
+                            color_id,
+                            palette_colors_count,
+                        })?;
             } else {
                 let byte_per_pixel = bits_per_pixel / 8;
 
                 if byte_per_pixel == 4 {
-                    red = u8.parse_next(i)?;
-                    green = u8.parse_next(i)?;
-                    blue = u8.parse_next(i)?;
-                    alpha = u8.parse_next(i)?;
+                    red = u8.parse_next(i).at(i)?;
+                    green = u8.parse_next(i).at(i)?;
+                    blue = u8.parse_next(i).at(i)?;
+                    alpha = u8.parse_next(i).at(i)?;
                 } else {
                     let rgba;
                     if byte_per_pixel == 3 {
                         // 24 bits is 16 bit color data (big endian) with 8 bit alpha
-                        alpha = u8.parse_next(i)?;
-                        rgba = be_u16.parse_next(i)?;
+                        alpha = u8.parse_next(i).at(i)?;
+                        rgba = be_u16.parse_next(i).at(i)?;
                     } else {
                         // for the 16 bit images, the value is little endian
-                        rgba = le_u16.parse_next(i)?;
+                        rgba = le_u16.parse_next(i).at(i)?;
                     }
                     if pixel_format == 0x13 {
                         // color is 16 bit (4:4:4:4) abgr
@@ -195,34 +218,144 @@ pub fn image_parse(i: &mut Stream) -> PResult<Image> {
     })
 }
 
-pub fn params_parser(i: &mut Stream, max_size: usize) -> PResult<Params> {
+// Compressed image has its own header:
+// width: u16, height: u16, bits per pixel: u16 (only 32), compressed data size: u32
+// Data is a sequence of RLE packets of RGBA pixels (alpha is inverted like in other formats).
+// Packet starts with a byte, if high bit is set next pixel is repeated (low bits + 1) times,
+// otherwise (low bits + 1) pixels are stored as is. Packets can cross rows.
+// It is the format of `parseCompressedImage` of watchface-js, which this parser is ported from.
+// TODO: check with an image from a real watchface, tests only have hand-made images
+pub fn compressed_image_parse(i: &mut Stream, offset: usize) -> Result<Image, WatchfaceError> {
+    let pixel_format = 0x65;
+    let width = le_u16.parse_next(i).at(i)?;
+    let height = le_u16.parse_next(i).at(i)?;
+    let bits_per_pixel = le_u16.parse_next(i).at(i)?;
+    let data_size = le_u32.parse_next(i).at(i)?;
+
+    if bits_per_pixel != 32 {
+        return Err(WatchfaceError::UnsupportedPixelFormat {
+            offset,
+            pixel_format,
+            bits_per_pixel,
+            palette_colors_count: 0,
+        });
+    }
+
+    let data_start = i.location();
+    let expected = width as usize * height as usize;
+    let mut pixels = Vec::with_capacity(4 * expected);
+    let pixel = |i: &mut Stream, pixels: &mut Vec<u8>| -> Result<(), WatchfaceError> {
+        let color = token::take(4usize).parse_next(i).at(i)?;
+        pixels.extend([color[0], color[1], color[2], 0xFF - color[3]]);
+        Ok(())
+    };
+
+    while i.location() - data_start < data_size as usize {
+        let packet = u8.parse_next(i).at(i)?;
+        let count = (packet & 0x7F) as usize + 1;
+        if packet & 0x80 == 0x80 {
+            pixel(i, &mut pixels)?;
+            let repeated = pixels[pixels.len() - 4..].to_vec();
+            for _ in 1..count {
+                pixels.extend(&repeated);
+            }
+        } else {
+            for _ in 0..count {
+                pixel(i, &mut pixels)?;
+            }
+        }
+    }
+
+    if pixels.len() != 4 * expected || i.location() - data_start != data_size as usize {
+        return Err(WatchfaceError::WrongCompressedData {
+            offset,
+            pixels_count: pixels.len() / 4,
+            expected,
+        });
+    }
+
+    Ok(Image {
+        width,
+        height,
+        bits_per_pixel,
+        pixel_format,
+        pixels,
+    })
+}
+
+// 32 bit image has a shorter header without palette fields:
+// width: u16, height: u16, row size: u16, bits per pixel: u16 (only 32)
+// Pixels are stored as BGRA and alpha is not inverted (0xFF is opaque)
+// It is the format of `parse32BitImage` of watchface-js, which this parser is ported from.
+// TODO: check with an image from a real watchface, tests only have hand-made images
+pub fn bit32_image_parse(i: &mut Stream, offset: usize) -> Result<Image, WatchfaceError> {
+    let pixel_format = 0xFFFF;
+    let width = le_u16.parse_next(i).at(i)?;
+    let height = le_u16.parse_next(i).at(i)?;
+    let row_size = le_u16.parse_next(i).at(i)?;
+    let bits_per_pixel = le_u16.parse_next(i).at(i)?;
+
+    if bits_per_pixel != 32 {
+        return Err(WatchfaceError::UnsupportedPixelFormat {
+            offset,
+            pixel_format,
+            bits_per_pixel,
+            palette_colors_count: 0,
+        });
+    }
+
+    let expected = (width as u32 * 4) as u16;
+    if expected != row_size {
+        return Err(WatchfaceError::WrongRowSize {
+            offset,
+            row_size,
+            expected,
+        });
+    }
+
+    let data = token::take(row_size as usize * height as usize)
+        .parse_next(i)
+        .at(i)?;
+    let pixels = data
+        .chunks(4)
+        .flat_map(|p| [p[2], p[1], p[0], p[3]])
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        bits_per_pixel,
+        pixel_format,
+        pixels,
+    })
+}
+
+pub fn params_parser(i: &mut Stream, max_size: usize, bits: u32) -> Result<Params, WatchfaceError> {
     let mut prev = i.location();
     let mut bytes_left = max_size;
-    let mut params = Params::from(HashMap::new());
+    let mut params = Params::new();
     while bytes_left > 0 {
-        match param_parser.parse_next(i) {
-            Ok(o) => {
-                let (key, val) = o;
-                match params.entry(key) {
-                    Entry::Occupied(mut occupied) => {
-                        occupied.get_mut().push(val);
-                    }
-                    Entry::Vacant(vacant) => {
-                        vacant.insert(vec![val]);
-                    }
-                }
-                bytes_left -= i.location() - prev;
-                prev = i.location();
-            }
-            Err(e) => return Err(e),
-        }
+        let (key, val) = param_parser(i, bits)?;
+        let span = Span {
+            offset: prev,
+            length: i.location() - prev,
+        };
+        params.push(key, val, Some(span));
+        bytes_left =
+            bytes_left
+                .checked_sub(span.length)
+                .ok_or(WatchfaceError::WrongParamsSize {
+                    offset: prev,
+                    size: max_size,
+                })?;
+        prev = i.location();
     }
     Ok(params)
 }
 
-pub fn param_parser(i: &mut Stream) -> PResult<(u8, Param)> {
+pub fn param_parser(i: &mut Stream, bits: u32) -> Result<(u8, Param), WatchfaceError> {
     // Read parameters info
-    let (field_descriptor, _) = variable_width_value_parser(i)?;
+    let (field_descriptor, _) = variable_width_value_parser(i, bits).at(i)?;
 
     let key = (field_descriptor >> 3) as u8;
     let has_child = field_descriptor & 0x02 == 0x02;
@@ -231,17 +364,17 @@ pub fn param_parser(i: &mut Stream) -> PResult<(u8, Param)> {
     let value;
     let is_float = field_descriptor & 0x05 == 0x05;
     if is_float {
-        value = Param::Float(le_f32.parse_next(i)?);
+        value = Param::Float(le_f32.parse_next(i).at(i)?);
     } else {
         // variable width value
-        let (field_value, _) = variable_width_value_parser(i)?;
+        let (field_value, _) = variable_width_value_parser(i, bits).at(i)?;
 
         if has_child {
             // When node has Child, field value is size of Child
 
             let child_size = field_value as usize;
             // Recursive call to read Child data
-            let child = params_parser(i, child_size)?;
+            let child = params_parser(i, child_size, bits)?;
             value = Param::Child(child);
         } else {
             value = Param::Number(field_value);
@@ -259,98 +392,217 @@ pub fn bytes_to_usize(bytes: &[u8]) -> usize {
     usize::from_le_bytes(bytes)
 }
 
-pub fn bin_parser<T>(mut i: Located<&[u8]>) -> PResult<Watchface<T>>
-where
-    T: WatchfaceParams,
-    Option<T>: Transform + Preview,
-{
-    // TODO: check signature
-    let _signature = token::take(4usize).parse_next(&mut i)?;
-    let _header = token::take(75usize).parse_next(&mut i)?;
-    let _buffer_size = le_u32.parse_next(&mut i)?;
-    let info_size = le_u32.parse_next(&mut i)?;
-    let parameter_info = params_parser(&mut i, info_size as usize)?;
+pub fn header_parser(i: &mut Stream) -> Result<WatchfaceHeader, WatchfaceError> {
+    let signature = token::take(4usize).parse_next(i).at(i)?;
+    if signature != SIGNATURE {
+        return Err(WatchfaceError::InvalidSignature {
+            signature: signature.to_vec(),
+        });
+    }
+    let raw: [u8; 75] = token::take(75usize)
+        .parse_next(i)
+        .at(i)?
+        .try_into()
+        .unwrap();
+
+    let u16_at = |offset: usize| u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(raw[offset..offset + 4].try_into().unwrap());
+    Ok(WatchfaceHeader {
+        signature: *SIGNATURE,
+        version: u16_at(0),
+        format_id: raw[7],
+        unknown8: u32_at(8),
+        device_id: u16_at(12),
+        watchface_id: u32_at(14),
+        unknown18: u32_at(18),
+        raw,
+    })
+}
 
-    // First parameter info contains parameters size and images count
-    use Param::*;
+pub fn dial_header_parser(i: &mut Stream) -> Result<DialHeader, WatchfaceError> {
+    let signature = token::take(DIAL_SIGNATURE.len()).parse_next(i).at(i)?;
+    if signature != DIAL_SIGNATURE {
+        return Err(WatchfaceError::InvalidSignature {
+            signature: signature.to_vec(),
+        });
+    }
+    let raw = token::take(25usize)
+        .parse_next(i)
+        .at(i)?
+        .try_into()
+        .unwrap();
+    Ok(DialHeader { raw })
+}
+
+// Parameters part of a file before they are transformed
+#[derive(Debug, PartialEq)]
+pub struct RawParameters {
+    pub buffer_size: u32,
+    pub info: Params,
+    // Every section is a child param with span of its block
+    pub sections: Params,
+    pub sections_start: usize,
+    pub sections_size: usize,
+    pub images_count: usize,
+}
+
+// Parses buffer size, parameters info and sections, stops after the sections
+pub fn parameters_parser(i: &mut Stream, bits: u32) -> Result<RawParameters, WatchfaceError> {
+    let buffer_size = le_u32.parse_next(i).at(i)?;
+    let info_size = le_u32.parse_next(i).at(i)?;
+    let info = params_parser(i, info_size as usize, bits)?;
 
-    let first_parameter = match &parameter_info.get(&1).unwrap()[0] {
-        Child(child) => child,
-        _ => panic!("First param should be child param"),
+    // First parameter info contains parameters size and images count
+    let first_parameter = child_param(info.get(&1).map_or(&[], |value| &value[..]))
+        .map_err(|e| e.in_field("1").in_field("ParametersInfo"))?;
+    let info_value = |params: &Params, key: u8| -> Result<usize, WatchfaceError> {
+        let mut value: usize = 0;
+        value
+            .transform(params.get(&key).map_or(&[], |value| &value[..]))
+            .map(|_| value)
+            .map_err(|e| e.in_field(&key.to_string()))
     };
 
-    let mut parameters_size: usize = 0;
-    parameters_size.transform(first_parameter.get(&1).unwrap());
-    let mut images_count: usize = 0;
-    images_count.transform(first_parameter.get(&2).unwrap());
+    let sections_size =
+        info_value(first_parameter, 1).map_err(|e| e.in_field("1").in_field("ParametersInfo"))?;
+    let images_count =
+        info_value(first_parameter, 2).map_err(|e| e.in_field("1").in_field("ParametersInfo"))?;
 
-    let mut parameters: Option<T> = None;
-    let mut all_params = HashMap::new();
+    let mut sections = Params::new();
+    let sections_start = i.location();
     let params_start = i.checkpoint();
 
-    for (key, value) in parameter_info.iter() {
+    for (key, value) in info.iter() {
         if *key == 1 {
             continue;
         }
 
         i.reset(&params_start);
 
-        let subvalue = match value.get(0).unwrap() {
-            Child(child) => child,
-            _ => panic!("First param should be child param"), // TODO: use adequate messages in all panics
-        };
-
-        let mut offset: usize = 0;
-        offset.transform(subvalue.get(&1).unwrap());
-        let mut size: usize = 0;
-        size.transform(subvalue.get(&2).unwrap());
+        let in_info = |e: WatchfaceError| e.in_field(&key.to_string()).in_field("ParametersInfo");
+        let subvalue = child_param(value).map_err(in_info)?;
+        let offset = info_value(subvalue, 1).map_err(in_info)?;
+        let size = info_value(subvalue, 2).map_err(in_info)?;
 
-        i.next_slice(offset);
-        let params = params_parser(&mut i, size)?;
-        all_params.insert(*key, vec![Param::Child(params)]);
+        token::take(offset).parse_next(i).at(i)?;
+        let params = params_parser(i, size, bits)?;
+        let span = Span {
+            offset: sections_start + offset,
+            length: size,
+        };
+        sections.push(*key, Param::Child(params), Some(span));
     }
 
-    let params = &vec![Param::Child(all_params)];
-    parameters.transform(params);
-
     i.reset(&params_start);
-    i.next_slice(parameters_size);
+    token::take(sections_size).parse_next(i).at(i)?;
+
+    Ok(RawParameters {
+        buffer_size,
+        info,
+        sections,
+        sections_start,
+        sections_size,
+        images_count,
+    })
+}
 
-    let images_info_size = 4 * images_count;
-    let images_info = token::take(images_info_size).parse_next(&mut i)?;
+pub fn bin_parser<T>(mut i: Located<&[u8]>) -> Result<Watchface<T>, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform + Preview,
+{
+    let bytes = *i.as_ref();
+    let i = &mut i;
+    let header = T::Header::parse(i)?;
+    let parameters_start = i.location();
+    let raw_parameters = parameters_parser(i, T::VALUE_BITS)?;
+    let images_count = raw_parameters.images_count;
+    let sections = raw_parameters
+        .sections
+        .entries()
+        .iter()
+        .map(|entry| {
+            let blocks = entry
+                .spans
+                .iter()
+                .flat_map(|span| &bytes[span.offset..span.offset + span.length]);
+            (entry.key, blocks.copied().collect())
+        })
+        .collect();
+
+    let mut parameters: Option<T> = None;
+    parameters.transform(&[Param::Child(raw_parameters.sections)])?;
+
+    let images_info_start = i.location();
+    let images_info_size = images_count
+        .checked_mul(4)
+        .ok_or(WatchfaceError::UnexpectedEnd {
+            offset: bytes.len(),
+        })?;
+    let images_info = token::take(images_info_size).parse_next(i).at(i)?;
 
     let images_start = i.checkpoint();
 
     // Load each image
     let mut images = vec![];
+    let mut encoded_images = vec![];
     for offset_index in 0..images_count {
         let image_offset = bytes_to_usize(&images_info[offset_index * 4..offset_index * 4 + 4]);
         i.reset(&images_start);
-        i.next_slice(image_offset);
-        let image = image_parse(&mut i)?;
+        token::take(image_offset).parse_next(i).at(i)?;
+        let image_start = i.location();
+        let image = image_parse(i)?;
         images.push(image);
+        encoded_images.push(bytes[image_start..i.location()].to_vec());
     }
 
-    Ok(Watchface { parameters, images })
+    let source = WatchfaceSource {
+        parameters: parameters.untransform(),
+        parameters_bytes: bytes[parameters_start..images_info_start].to_vec(),
+        sections,
+        images: images.clone(),
+        encoded_images,
+        images_bytes: bytes[images_info_start..].to_vec(),
+    };
+
+    Ok(Watchface {
+        header,
+        parameters,
+        images,
+        source: Some(source),
+    })
+}
+
+trait AtOffset<O> {
+    fn at(self, i: &Stream) -> Result<O, WatchfaceError>;
+}
+
+impl<O> AtOffset<O> for PResult<O> {
+    // Winnow errors here could only mean that there is not enough data
+    fn at(self, i: &Stream) -> Result<O, WatchfaceError> {
+        self.map_err(|_| WatchfaceError::UnexpectedEnd {
+            offset: i.location(),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use {super::*, std::collections::HashMap};
+    use {super::*, crate::miband::MiBandParams};
 
     #[test]
     fn parse_keys_and_values() {
         let bytes: Vec<u8> = vec![0x08, 0x04, 0x10, 0x6B];
 
-        let result = params_parser(&mut Located::new(&bytes), bytes.len());
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64);
         assert!(result.is_ok());
         if let Ok(result) = result {
             assert_eq!(
                 result,
-                Params::from(HashMap::from([
+                Params::from([
                     (1, vec![Param::Number(0x04)]),
                     (2, vec![Param::Number(0x6B)]),
-                ]))
+                ])
             )
         }
     }
@@ -359,18 +611,18 @@ mod tests {
     fn parse_nested_structure() {
         let bytes: Vec<u8> = vec![0x0A, 0x05, 0x08, 0xBC, 0x04, 0x10, 0x6B];
 
-        let result = params_parser(&mut Located::new(&bytes), bytes.len());
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64);
         assert!(result.is_ok());
         if let Ok(result) = result {
             assert_eq!(
                 result,
-                Params::from(HashMap::from([(
+                Params::from([(
                     1,
-                    vec![Param::Child(Params::from(HashMap::from([
+                    vec![Param::Child(Params::from([
                         (1, vec![Param::Number(0x023C)]),
                         (2, vec![Param::Number(0x6B)]),
-                    ])))]
-                ),]))
+                    ]))]
+                ),])
             )
         }
     }
@@ -379,30 +631,66 @@ mod tests {
     fn parse_lists() {
         let bytes: Vec<u8> = vec![0x08, 0x04, 0x08, 0x7F, 0x10, 0x6B];
 
-        let result = params_parser(&mut Located::new(&bytes), bytes.len());
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64);
         assert!(result.is_ok());
         if let Ok(result) = result {
             assert_eq!(
                 result,
-                Params::from(HashMap::from([
+                Params::from([
                     (1, vec![Param::Number(0x04), Param::Number(0x7F)]),
                     (2, vec![Param::Number(0x6B)]),
-                ]))
+                ])
             )
         }
     }
 
+    #[test]
+    fn parse_keeps_keys_order_and_spans() {
+        let bytes: Vec<u8> = vec![0x10, 0x6B, 0x0A, 0x02, 0x08, 0x01, 0x10, 0xBC, 0x04];
+
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64).unwrap();
+        assert_eq!(result.keys().collect::<Vec<_>>(), [&2, &1]);
+        let entries = result.entries();
+        assert_eq!(
+            entries[0].spans,
+            [
+                Span {
+                    offset: 0,
+                    length: 2
+                },
+                Span {
+                    offset: 6,
+                    length: 3
+                }
+            ]
+        );
+        assert_eq!(
+            entries[1].spans,
+            [Span {
+                offset: 2,
+                length: 4
+            }]
+        );
+        let Param::Child(child) = &entries[1].values[0] else {
+            panic!("child expected");
+        };
+        assert_eq!(
+            child.entries()[0].spans,
+            [Span {
+                offset: 4,
+                length: 2
+            }]
+        );
+    }
+
     #[test]
     fn parse_multi_byte_id() {
         let bytes: Vec<u8> = vec![0x80, 0x02, 0x04];
 
-        let result = params_parser(&mut Located::new(&bytes), bytes.len());
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64);
         assert!(result.is_ok());
         if let Ok(result) = result {
-            assert_eq!(
-                result,
-                Params::from(HashMap::from([(32, vec![Param::Number(0x04)]),]))
-            )
+            assert_eq!(result, Params::from([(32, vec![Param::Number(0x04)]),]))
         }
     }
 
@@ -412,27 +700,311 @@ mod tests {
             0x0A, 0x0A, 0x0D, 0x00, 0x00, 0xA0, 0x3F, 0x3D, 0x00, 0x00, 0xB4, 0x43,
         ];
 
-        let result = params_parser(&mut Located::new(&bytes), bytes.len());
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64);
         assert!(result.is_ok());
         if let Ok(result) = result {
             assert_eq!(
                 result,
-                Params::from(HashMap::from([(
+                Params::from([(
                     1,
-                    vec![Param::Child(Params::from(HashMap::from([
+                    vec![Param::Child(Params::from([
                         (1, vec![Param::Float(1.25)]),
                         (7, vec![Param::Float(360.0)])
-                    ])),)]
-                ),]))
+                    ]),)]
+                ),])
             )
         }
     }
 
+    #[test]
+    fn parse_compressed_image() {
+        let bytes: Vec<u8> = vec![
+            0x42, 0x4D, 0x65, 0x00, // Signature and pixel format
+            0x03, 0x00, 0x02, 0x00, 0x20, 0x00, // width: 3, height: 2, bits per pixel: 32
+            0x0E, 0x00, 0x00, 0x00, // compressed data size: 14
+            0x83, 0x11, 0x21, 0x31, 0x00, // 4 pixels of the same color
+            0x01, 0x12, 0x22, 0x32, 0xFF, 0x13, 0x23, 0x33, 0x80, // 2 different pixels
+        ];
+
+        let result = image_parse(&mut Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Ok(Image {
+                pixels: vec![
+                    0x11, 0x21, 0x31, 0xFF, 0x11, 0x21, 0x31, 0xFF, 0x11, 0x21, 0x31,
+                    0xFF, // 1st row
+                    0x11, 0x21, 0x31, 0xFF, 0x12, 0x22, 0x32, 0x00, 0x13, 0x23, 0x33,
+                    0x7F, // 2nd row
+                ],
+                width: 3,
+                height: 2,
+                bits_per_pixel: 32,
+                pixel_format: 0x65,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_compressed_image_with_wrong_size() {
+        let bytes: Vec<u8> = vec![
+            0x42, 0x4D, 0x65, 0x00, 0x03, 0x00, 0x01, 0x00, 0x20, 0x00, 0x05, 0x00, 0x00, 0x00,
+            0x83, 0x11, 0x21, 0x31, 0x00,
+        ];
+
+        let result = image_parse(&mut Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::WrongCompressedData {
+                offset: 0,
+                pixels_count: 4,
+                expected: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_32_bit_image() {
+        let bytes: Vec<u8> = vec![
+            0x42, 0x4D, 0xFF, 0xFF, // Signature and pixel format
+            0x02, 0x00, 0x01, 0x00, 0x08, 0x00, 0x20,
+            0x00, // width: 2, height: 1, row size: 8
+            0x31, 0x21, 0x11, 0xFF, 0x32, 0x22, 0x12, 0x40, // BGRA pixels
+        ];
+
+        let result = image_parse(&mut Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Ok(Image {
+                pixels: vec![0x11, 0x21, 0x31, 0xFF, 0x12, 0x22, 0x32, 0x40],
+                width: 2,
+                height: 1,
+                bits_per_pixel: 32,
+                pixel_format: 0xFFFF,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_truncated_params() {
+        let bytes: Vec<u8> = vec![0x0A, 0x05, 0x08, 0xBC, 0x04];
+
+        let result = params_parser(&mut Located::new(&bytes), bytes.len(), 64);
+        assert_eq!(result, Err(WatchfaceError::UnexpectedEnd { offset: 5 }));
+    }
+
+    #[test]
+    fn parse_invalid_image_signature() {
+        let bytes: Vec<u8> = vec![0x00, 0x00, 0x42, 0x4D];
+
+        let result = image_parse(&mut Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::InvalidImageSignature {
+                offset: 0,
+                signature: 0x0000
+            })
+        );
+    }
+
+    #[test]
+    fn parse_header() {
+        let bytes = [&SIGNATURE[..], &DEFAULT_HEADER].concat();
+
+        let result = header_parser(&mut Located::new(&bytes[..]));
+        assert_eq!(result, Ok(WatchfaceHeader::default()));
+    }
+
+    #[test]
+    fn parse_invalid_watchface_signature() {
+        let bytes = [&b"HMDIAL\0"[..], &DEFAULT_HEADER].concat();
+
+        let result: Result<Watchface<MiBandParams>, _> = bin_parser(Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::InvalidSignature {
+                signature: b"HMDI".to_vec()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_negative_images_count() {
+        let bytes: Vec<u8> = [
+            &SIGNATURE[..],
+            &DEFAULT_HEADER,
+            &[
+                0x00, 0x00, 0x00, 0x00, // Size of biggest param
+                0x0F, 0x00, 0x00, 0x00, // Size of params info: 15
+                // size of params: 0, imagesCount: -1
+                0x0a, 0x0D, 0x08, 0x00, 0x10, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                0x01,
+            ],
+        ]
+        .concat();
+
+        let result: Result<Watchface<MiBandParams>, _> = bin_parser(Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::ValueOutOfRange {
+                path: "ParametersInfo.1.2".to_string(),
+                value: -1
+            })
+        );
+    }
+
+    #[test]
+    fn parse_too_big_images_count() {
+        let bytes: Vec<u8> = [
+            &SIGNATURE[..],
+            &DEFAULT_HEADER,
+            &[
+                0x00, 0x00, 0x00, 0x00, // Size of biggest param
+                0x0E, 0x00, 0x00, 0x00, // Size of params info: 14
+                // size of params: 0, imagesCount: 2^62
+                0x0a, 0x0C, 0x08, 0x00, 0x10, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x40,
+            ],
+        ]
+        .concat();
+
+        let result: Result<Watchface<MiBandParams>, _> = bin_parser(Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::UnexpectedEnd {
+                offset: bytes.len()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_unsupported_pixel_format() {
+        let bytes: Vec<u8> = vec![
+            0x42, 0x4D, 0x64, 0x00, 0x02, 0x00, 0x01, 0x00, 0x08, 0x00, 0x20, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+
+        let result = image_parse(&mut Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::UnsupportedPixelFormat {
+                offset: 0,
+                pixel_format: 0x64,
+                bits_per_pixel: 32,
+                palette_colors_count: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_wrong_row_size() {
+        let bytes: Vec<u8> = vec![
+            0x42, 0x4D, 0x10, 0x00, 0x02, 0x00, 0x01, 0x00, 0x09, 0x00, 0x20, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+
+        let result = image_parse(&mut Located::new(&bytes[..]));
+        assert_eq!(
+            result,
+            Err(WatchfaceError::WrongRowSize {
+                offset: 0,
+                row_size: 9,
+                expected: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn transform_reports_field_path() {
+        let params = Params::from([(
+            4,
+            vec![Param::Child(Params::from([(
+                1,
+                vec![Param::Child(Params::from([(
+                    1,
+                    vec![Param::Child(Params::from([(5, vec![Param::Number(3)])]))],
+                )]))],
+            )]))],
+        )]);
+
+        let mut result: Option<MiBandParams> = None;
+        assert_eq!(
+            result.transform(&[Param::Child(params)]),
+            Err(WatchfaceError::WrongAlignment {
+                path: "Activity.Steps.Number.Alignment".to_string(),
+                value: 3
+            })
+        );
+    }
+
+    #[test]
+    fn transform_keeps_unknown_keys() {
+        let params = Params::from([
+            (
+                2,
+                vec![Param::Child(Params::from([(
+                    30,
+                    vec![Param::Float(1.5), Param::Number(-1)],
+                )]))],
+            ),
+            (
+                13,
+                vec![Param::Child(Params::from([(1, vec![Param::Number(2)])]))],
+            ),
+        ]);
+
+        let mut result: Option<MiBandParams> = None;
+        result.transform(&[Param::Child(params.clone())]).unwrap();
+        let result_params = result.as_ref().unwrap();
+        assert_eq!(result_params.extra.0.keys().collect::<Vec<_>>(), [&13]);
+        assert_eq!(
+            result_params.background.as_ref().unwrap().extra.0[&30],
+            [Param::Float(1.5), Param::Number(-1)]
+        );
+        assert_eq!(result.untransform(), [Param::Child(params)]);
+    }
+
+    #[test]
+    fn transform_reports_wrong_param_type() {
+        let params = Params::from([(
+            2,
+            vec![Param::Child(Params::from([(1, vec![Param::Number(1)])]))],
+        )]);
+
+        let mut result: Option<MiBandParams> = None;
+        assert_eq!(
+            result.transform(&[Param::Child(params)]),
+            Err(WatchfaceError::WrongParamType {
+                path: "Background.Image".to_string(),
+                expected: "child"
+            })
+        );
+    }
+
+    #[test]
+    fn parse_palette_image_rows_from_new_bytes() {
+        // 1x2 image with 1 bit per pixel, every row takes its own byte
+        let bytes: Vec<u8> = vec![
+            0x42, 0x4D, 0x64, 0x00, // Signature, palette pixel format
+            0x01, 0x00, 0x02, 0x00, // Width: 1, height: 2
+            0x01, 0x00, 0x01, 0x00, // Row size: 1, bits per pixel: 1
+            0x02, 0x00, 0x00, 0x00, // Palette colors count: 2, no transparent color
+            0xFF, 0x00, 0x00, 0x00, // Palette color 0: red
+            0x00, 0xFF, 0x00, 0x00, // Palette color 1: green
+            0x00, // 1st row: color 0
+            0x80, // 2nd row: color 1
+        ];
+
+        let image = image_parse(&mut Located::new(&bytes)).unwrap();
+        assert_eq!(
+            image.pixels,
+            vec![0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF]
+        );
+    }
+
     #[test]
     fn read_single_byte_value() {
         let bytes: Vec<u8> = vec![0x73];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 64);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
             assert_eq!((value, value_size), (0x73, 1),)
@@ -443,7 +1015,7 @@ mod tests {
     fn read_multi_byte_value() {
         let bytes: Vec<u8> = vec![0xF3, 0x42];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 64);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
             assert_eq!((value, value_size), (0x2173, 2),)
@@ -454,7 +1026,7 @@ mod tests {
     fn read_negative_values() {
         let bytes: Vec<u8> = vec![0xF3, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 64);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
             assert_eq!((value, value_size), (-13, 10),)
@@ -465,15 +1037,10 @@ mod tests {
     fn read_32bit_negative_values() {
         let bytes: Vec<u8> = vec![0xF3, 0xFF, 0xFF, 0xFF, 0x0F];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 32);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
-            assert_eq!(
-                // TODO: implement 32 bit reading because it is needed for UIHH_BIPU_GTS2MINI format
-                // we can live without it for now
-                (value as i32, value_size),
-                (-13, 5),
-            )
+            assert_eq!((value, value_size), (-13, 5),)
         }
     }
 
@@ -481,7 +1048,7 @@ mod tests {
     fn read_31_bit_value() {
         let bytes: Vec<u8> = vec![0x80, 0x80, 0x80, 0x80, 0x04];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 64);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
             assert_eq!((value as i32, value_size), (1073741824, 5),)
@@ -492,7 +1059,7 @@ mod tests {
     fn read_32_bit_value() {
         let bytes: Vec<u8> = vec![0x80, 0x80, 0x80, 0x80, 0x08];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 64);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
             assert_eq!((value, value_size), (2147483648, 5),)
@@ -503,7 +1070,7 @@ mod tests {
     fn read_33_bit_value() {
         let bytes: Vec<u8> = vec![0x80, 0x80, 0x80, 0x80, 0x10];
 
-        let result = variable_width_value_parser(&mut Located::new(&bytes));
+        let result = variable_width_value_parser(&mut Located::new(&bytes), 64);
         assert!(result.is_ok());
         if let Ok((value, value_size)) = result {
             assert_eq!((value, value_size), (4294967296, 5),)
@@ -512,34 +1079,34 @@ mod tests {
 
     #[test]
     fn write_small_value_on_one_byte() {
-        assert_eq!(write_variable_width_value(0x73), vec![0x73],)
+        assert_eq!(write_variable_width_value(0x73, 64), vec![0x73],)
     }
 
     #[test]
     fn write_bigger_values_on_multiple_bytes() {
-        assert_eq!(write_variable_width_value(0x2173), vec![0xF3, 0x42],)
+        assert_eq!(write_variable_width_value(0x2173, 64), vec![0xF3, 0x42],)
     }
 
     #[test]
     fn write_negative_values() {
         assert_eq!(
-            write_variable_width_value(-13),
+            write_variable_width_value(-13, 64),
             vec![0xF3, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
         )
     }
 
-    // #[test]
-    // fn write_32bit_negative_values () {
-    //     assert_eq!(
-    //         write_variable_width_value(-13, 32),
-    //         vec![0xF3, 0xFF, 0xFF, 0xFF, 0x0F],
-    //     )
-    // }
+    #[test]
+    fn write_32bit_negative_values() {
+        assert_eq!(
+            write_variable_width_value(-13, 32),
+            vec![0xF3, 0xFF, 0xFF, 0xFF, 0x0F],
+        )
+    }
 
     #[test]
     fn write_31_bit_value() {
         assert_eq!(
-            write_variable_width_value(1073741824),
+            write_variable_width_value(1073741824, 64),
             vec![0x80, 0x80, 0x80, 0x80, 0x04],
         )
     }
@@ -547,7 +1114,7 @@ mod tests {
     #[test]
     fn write_32_bit_value() {
         assert_eq!(
-            write_variable_width_value(2147483648),
+            write_variable_width_value(2147483648, 64),
             vec![0x80, 0x80, 0x80, 0x80, 0x08],
         )
     }
@@ -555,7 +1122,7 @@ mod tests {
     #[test]
     fn write_33_bit_value() {
         assert_eq!(
-            write_variable_width_value(4294967296),
+            write_variable_width_value(4294967296, 64),
             vec![0x80, 0x80, 0x80, 0x80, 0x10],
         )
     }
@@ -1,9 +1,11 @@
 use {
     crate::common::*,
     crate::miband::*,
+    crate::{gtr, miband4},
     std::f32::consts::PI,
     tiny_skia::{
-        FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform as Transform2, BYTES_PER_PIXEL,
+        ColorU8, FillRule, FilterQuality, Paint, Path, PathBuilder, Pixmap, PixmapPaint, Rect,
+        Stroke, Transform as Transform2, BYTES_PER_PIXEL,
     },
 };
 
@@ -19,8 +21,8 @@ pub trait Preview {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords>;
 }
 
@@ -50,7 +52,7 @@ fn compute_position_with_aligment(
 fn status_image_get_images(
     status_image: &Option<StatusImage>,
     param: bool,
-    _images: &Vec<Image>,
+    _images: &[Image],
 ) -> Vec<ImageWithCoords> {
     let mut res = vec![];
 
@@ -84,7 +86,7 @@ fn status_image_get_images(
 fn text_get_images(
     number: &NumberInRect,
     image_ids: Vec<u32>,
-    images: &Vec<Image>,
+    images: &[Image],
 ) -> Vec<ImageWithCoords> {
     let mut res = vec![];
 
@@ -104,7 +106,7 @@ fn text_get_images(
     let mut x = compute_position_with_aligment(
         number.top_left_x,
         number.bottom_right_x,
-        text_width.try_into().unwrap(),
+        text_width.into(),
         aligment,
     );
 
@@ -154,11 +156,11 @@ fn number_get_image_ids(
         }
         if let Some(min_width) = min_width {
             while int_part_image_ids.len() < min_width {
-                int_part_image_ids.push(image_index.0 + 0);
+                int_part_image_ids.push(image_index.0);
             }
         }
         if int_part_image_ids.is_empty() {
-            int_part_image_ids.push(image_index.0 + 0);
+            int_part_image_ids.push(image_index.0);
         }
         int_part_image_ids.reverse();
         image_ids.append(&mut int_part_image_ids);
@@ -174,7 +176,7 @@ fn number_get_image_ids(
                     fract /= 10;
                 }
                 while fract_image_ids.len() < 2 {
-                    fract_image_ids.push(image_index.0 + 0);
+                    fract_image_ids.push(image_index.0);
                 }
                 fract_image_ids.reverse();
                 image_ids.append(&mut fract_image_ids);
@@ -184,10 +186,11 @@ fn number_get_image_ids(
     image_ids
 }
 
+#[allow(clippy::too_many_arguments)]
 fn number_get_images(
     number: &Option<NumberInRect>,
     param: f32,
-    images: &Vec<Image>,
+    images: &[Image],
     prefix_image_index: &Option<ImgId>,
     decimal_point_image_index: &Option<ImgId>,
     minus_image_index: &Option<ImgId>,
@@ -221,10 +224,11 @@ fn number_get_images(
     res
 }
 
+#[allow(clippy::too_many_arguments)]
 fn numbers_with_delimiters_get_images(
     number: &Option<NumberInRect>,
     params: &[f32],
-    images: &Vec<Image>,
+    images: &[Image],
     minus_image_index: &Option<ImgId>,
     delimiter_image_index: &Option<ImgId>,
     suffix_image_index: &Option<ImgId>,
@@ -267,32 +271,215 @@ fn numbers_with_delimiters_get_images(
     res
 }
 
+// Shapes are drawn only inside this square from the screen origin, it is bigger than screens
+// of all watches and keeps huge shapes from files from taking all the memory
+const MAX_DRAW_SIZE: f32 = 1024.;
+// Fixed point math of tiny-skia breaks on bigger coordinates, such shapes are skipped
+const MAX_COORDINATE: f32 = 16384.;
+
+// Pixmap for the visible part of the bounds, None when nothing of them can be seen
+fn clipped_pixmap(left: f32, top: f32, right: f32, bottom: f32) -> Option<(Pixmap, i32, i32)> {
+    if [left, top, right, bottom]
+        .iter()
+        .any(|value| value.abs() > MAX_COORDINATE)
+    {
+        return None;
+    }
+    let left = left.floor().max(0.);
+    let top = top.floor().max(0.);
+    let right = right.ceil().min(MAX_DRAW_SIZE);
+    let bottom = bottom.ceil().min(MAX_DRAW_SIZE);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    let pixmap = Pixmap::new((right - left) as u32, (bottom - top) as u32)?;
+    Some((pixmap, left as i32, top as i32))
+}
+
+// Only the area around the path is drawn, with margin for antialiasing
+fn draw_path(
+    path: &Path,
+    transform: Transform2,
+    color: &Color,
+    stroke: Option<&Stroke>,
+) -> Option<ImageWithCoords> {
+    let margin = stroke.map_or(0., |stroke| stroke.width / 2.) + 1.;
+    let bounds = path.clone().transform(transform)?.bounds();
+    let (mut pixmap, left, top) = clipped_pixmap(
+        bounds.left() - margin,
+        bounds.top() - margin,
+        bounds.right() + margin,
+        bounds.bottom() + margin,
+    )?;
+    let transform = transform.post_translate(-left as f32, -top as f32);
+
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.0, color.1, color.2, color.3);
+    paint.anti_alias = true;
+
+    match stroke {
+        Some(stroke) => pixmap.stroke_path(path, &paint, stroke, transform, None),
+        None => pixmap.fill_path(path, &paint, FillRule::Winding, transform, None),
+    }
+
+    Some(pixmap_image(&pixmap, left, top))
+}
+
+// Image is rotated by `angle` degrees clockwise around its `pivot` placed to `center`
+fn draw_rotated_image(
+    image: &Image,
+    pivot: (f32, f32),
+    center: (f32, f32),
+    angle: f32,
+) -> Option<ImageWithCoords> {
+    let mut source = Pixmap::new(image.width as u32, image.height as u32)?;
+    for (pixel, color) in source
+        .pixels_mut()
+        .iter_mut()
+        .zip(image.pixels.chunks_exact(4))
+    {
+        *pixel = ColorU8::from_rgba(color[0], color[1], color[2], color[3]).premultiply();
+    }
+
+    let transform = Transform2::from_translate(center.0, center.1)
+        .pre_rotate(angle)
+        .pre_translate(-pivot.0, -pivot.1);
+    let rect = Rect::from_xywh(0., 0., image.width as f32, image.height as f32)?;
+    let bounds = PathBuilder::from_rect(rect).transform(transform)?.bounds();
+    let (mut pixmap, left, top) = clipped_pixmap(
+        bounds.left() - 1.,
+        bounds.top() - 1.,
+        bounds.right() + 1.,
+        bounds.bottom() + 1.,
+    )?;
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bilinear,
+        ..Default::default()
+    };
+    pixmap.draw_pixmap(
+        0,
+        0,
+        source.as_ref(),
+        &paint,
+        transform.post_translate(-left as f32, -top as f32),
+        None,
+    );
+
+    Some(pixmap_image(&pixmap, left, top))
+}
+
+fn pixmap_image(pixmap: &Pixmap, x: i32, y: i32) -> ImageWithCoords {
+    // Pixmap keeps colors premultiplied by alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    ImageWithCoords {
+        x,
+        y,
+        image_type: ImageType::Image(Image {
+            pixels,
+            width: pixmap.width() as u16,
+            height: pixmap.height() as u16,
+            bits_per_pixel: (BYTES_PER_PIXEL * 8) as u16,
+            pixel_format: 0,
+        }),
+    }
+}
+
+// Angles are in degrees, clockwise from 12 o'clock
+fn circle_scale_point(circle_scale: &CircleScale, angle: f32) -> Option<(f32, f32)> {
+    let center_x = circle_scale.center_x? as f32;
+    let center_y = circle_scale.center_y? as f32;
+    let radius_x = circle_scale.radius_x? as f32;
+    let radius_y = circle_scale
+        .radius_y
+        .map_or(radius_x, |radius_y| radius_y as f32);
+    let angle = angle.to_radians();
+    Some((
+        center_x + radius_x * angle.sin(),
+        center_y - radius_y * angle.cos(),
+    ))
+}
+
+fn circle_scale_arc(circle_scale: &CircleScale, progress: u32) -> Option<Path> {
+    let start_angle = circle_scale.start_angle? as f32;
+    let end_angle = circle_scale.end_angle? as f32;
+    // Angles come from the file, full circle is enough for any of them
+    let sweep = ((end_angle - start_angle) * progress.min(100) as f32 / 100.).clamp(-360., 360.);
+    let end_angle = start_angle + sweep;
+
+    // Arc is drawn as lines, one per degree
+    let steps = ((end_angle - start_angle).abs().ceil() as usize).max(1);
+    let mut pb = PathBuilder::new();
+    let (x, y) = circle_scale_point(circle_scale, start_angle)?;
+    pb.move_to(x, y);
+    for step in 1..=steps {
+        let angle = start_angle + (end_angle - start_angle) * step as f32 / steps as f32;
+        let (x, y) = circle_scale_point(circle_scale, angle)?;
+        pb.line_to(x, y);
+    }
+    pb.finish()
+}
+
+impl Preview for Option<CircleScale> {
+    fn get_images(
+        &self,
+        _all_params: &Option<PreviewParams>,
+        params: &[ParamType],
+        _images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let Some(circle_scale) = &self {
+            if let Some(ParamType::U32(Some(progress))) = params.first() {
+                // Stroke of zero width would be drawn as a hairline
+                let width = circle_scale.width.filter(|width| *width > 0);
+                if let (Some(width), Some(color)) = (width, &circle_scale.color) {
+                    if let Some(path) = circle_scale_arc(circle_scale, *progress) {
+                        let stroke = Stroke {
+                            width: width as f32,
+                            ..Default::default()
+                        };
+                        res.extend(draw_path(
+                            &path,
+                            Transform2::identity(),
+                            color,
+                            Some(&stroke),
+                        ));
+                    }
+                }
+            }
+        }
+
+        res
+    }
+}
+
 impl Preview for Option<VectorShape> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        _images: &Vec<Image>,
+        params: &[ParamType],
+        _images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
-        if let Some(ParamType::U32(Some(value))) = params.get(0) {
+        if let Some(ParamType::U32(Some(value))) = params.first() {
             let value = *value;
             if let Some(ParamType::F32(Some(ref total_value))) = params.get(1) {
                 let total_value = *total_value;
                 if let Some(vector_shape) = self {
                     if let Some(color) = &vector_shape.color {
                         if let Some(center) = &vector_shape.center {
-                            if let Some(first) = &vector_shape.shape.get(0) {
+                            if let Some(first) = &vector_shape.shape.first() {
                                 let angle =
                                     (2. * PI * value as f32 / total_value - PI / 2.) * 180. / PI;
 
-                                // TODO: replace 126, 294 with sizes of watchface screen
-                                let mut pixmap = Pixmap::new(126, 294).unwrap();
-
-                                let mut paint = Paint::default();
-                                paint.set_color_rgba8(color.0, color.1, color.2, color.3);
-                                paint.anti_alias = true;
-
                                 let mut pb = PathBuilder::new();
                                 pb.move_to(first.x as f32, first.y as f32);
 
@@ -300,57 +487,19 @@ impl Preview for Option<VectorShape> {
                                     pb.line_to(point.x as f32, point.y as f32);
                                 }
                                 pb.close();
-                                let path = pb.finish().unwrap();
-
-                                let only_border =
-                                    if let Some(only_border) = vector_shape.only_border {
-                                        only_border
-                                    } else {
-                                        false
-                                    };
-
-                                if only_border {
-                                    let stroke = Stroke::default();
-                                    pixmap.stroke_path(
-                                        &path,
-                                        &paint,
-                                        &stroke,
-                                        Transform2::from_translate(
-                                            center.x as f32,
-                                            center.y as f32,
-                                        )
-                                        .pre_rotate(angle),
-                                        None,
-                                    );
-                                } else {
-                                    pixmap.fill_path(
-                                        &path,
-                                        &paint,
-                                        FillRule::Winding,
-                                        Transform2::from_translate(
-                                            center.x as f32,
-                                            center.y as f32,
-                                        )
-                                        .pre_rotate(angle),
-                                        None,
-                                    );
+                                let transform =
+                                    Transform2::from_translate(center.x as f32, center.y as f32)
+                                        .pre_rotate(angle);
+
+                                let stroke = Stroke::default();
+                                let stroke = match vector_shape.only_border {
+                                    Some(true) => Some(&stroke),
+                                    _ => None,
+                                };
+                                // Path is not built for degenerate shapes
+                                if let Some(path) = pb.finish() {
+                                    res.extend(draw_path(&path, transform, color, stroke));
                                 }
-
-                                let width = pixmap.width() as u16;
-                                let height = pixmap.height() as u16;
-                                let pixels = pixmap.take();
-
-                                res.push(ImageWithCoords {
-                                    x: 0,
-                                    y: 0,
-                                    image_type: ImageType::Image(Image {
-                                        pixels,
-                                        width,
-                                        height,
-                                        bits_per_pixel: (BYTES_PER_PIXEL * 8) as u16,
-                                        pixel_format: 0,
-                                    }),
-                                });
                             }
                         }
                     }
@@ -372,12 +521,49 @@ impl Preview for Option<VectorShape> {
     }
 }
 
+impl Preview for Option<gtr::ImageHand> {
+    fn get_images(
+        &self,
+        _all_params: &Option<PreviewParams>,
+        params: &[ParamType],
+        images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let (Some(ParamType::U32(Some(value))), Some(ParamType::F32(Some(total_value)))) =
+            (params.first(), params.get(1))
+        {
+            if let Some(hand) = self {
+                if let (Some(center), Some(image)) = (&hand.center, &hand.image) {
+                    if let Some(image_data) = image
+                        .image_index
+                        .as_ref()
+                        .and_then(|image_index| images.get(image_index.0 as usize))
+                    {
+                        let angle = 360. * (*value as f32 % total_value) / total_value;
+                        res.extend(draw_rotated_image(
+                            image_data,
+                            (image.x as f32, image.y as f32),
+                            (center.x as f32, center.y as f32),
+                            angle,
+                        ));
+                    }
+                }
+
+                res.append(&mut hand.center_image.get_images(&None, &[], images));
+            }
+        }
+
+        res
+    }
+}
+
 impl Preview for Option<ImageReference> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        _images: &Vec<Image>,
+        _params: &[ParamType],
+        _images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -399,13 +585,13 @@ impl Preview for Option<Background> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(background) = &self {
-            res.append(&mut background.image.get_images(all_params, &vec![], images));
+            res.append(&mut background.image.get_images(all_params, &[], images));
         }
 
         res
@@ -416,14 +602,14 @@ impl Preview for Option<ImageRange> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        _images: &Vec<Image>,
+        params: &[ParamType],
+        _images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(image_range) = self {
             if let Some(image_index) = &image_range.image_index {
-                if let Some(ParamType::U32(Some(param))) = params.get(0) {
+                if let Some(ParamType::U32(Some(param))) = params.first() {
                     res.push(ImageWithCoords {
                         x: image_range.x,
                         y: image_range.y,
@@ -441,13 +627,13 @@ impl Preview for Option<Steps> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(steps) = &self {
-            if let Some(ParamType::U32(Some(param))) = params.get(0) {
+            if let Some(ParamType::U32(Some(param))) = params.first() {
                 res.append(&mut number_get_images(
                     &steps.number,
                     *param as f32,
@@ -469,13 +655,13 @@ impl Preview for Option<Pulse> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(pulse) = &self {
-            if let Some(ParamType::U32(Some(param))) = params.get(0) {
+            if let Some(ParamType::U32(Some(param))) = params.first() {
                 res.append(&mut number_get_images(
                     &pulse.number,
                     *param as f32,
@@ -497,13 +683,13 @@ impl Preview for Option<Calories> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(calories) = &self {
-            if let Some(ParamType::U32(Some(param))) = params.get(0) {
+            if let Some(ParamType::U32(Some(param))) = params.first() {
                 res.append(&mut number_get_images(
                     &calories.number,
                     *param as f32,
@@ -525,13 +711,13 @@ impl Preview for Option<PAI> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(pai) = &self {
-            if let Some(ParamType::U32(Some(param))) = params.get(0) {
+            if let Some(ParamType::U32(Some(param))) = params.first() {
                 res.append(&mut number_get_images(
                     &pai.number,
                     *param as f32,
@@ -553,13 +739,13 @@ impl Preview for Option<Distance> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(distance) = &self {
-            if let Some(ParamType::F32(Some(param))) = params.get(0) {
+            if let Some(ParamType::F32(Some(param))) = params.first() {
                 res.append(&mut number_get_images(
                     &distance.number,
                     *param,
@@ -581,16 +767,18 @@ impl Preview for Option<Linear> {
     fn get_images(
         &self,
         _all_params: &Option<PreviewParams>,
-        params: &Vec<ParamType>,
-        _images: &Vec<Image>,
+        params: &[ParamType],
+        _images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(linear) = &self {
             if let Some(start_image_index) = &linear.start_image_index {
-                if let Some(ParamType::U32(Some(param))) = params.get(0) {
+                if let Some(ParamType::U32(Some(param))) = params.first() {
                     let progress = (*param as f32 / 100. * (linear.segments.len() - 1) as f32)
                         .round() as usize;
+                    // Progress could be more than 100%
+                    let progress = progress.min(linear.segments.len() - 1);
                     for i in 0..=progress {
                         res.push(ImageWithCoords {
                             x: linear.segments[i].x,
@@ -610,8 +798,8 @@ impl Preview for Option<HeartProgress> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -619,7 +807,12 @@ impl Preview for Option<HeartProgress> {
             if let Some(all_params_val) = &all_params {
                 res.append(&mut heart_progress.linear.get_images(
                     all_params,
-                    &vec![ParamType::U32(all_params_val.heart_progress)],
+                    &[ParamType::U32(all_params_val.heart_progress)],
+                    images,
+                ));
+                res.append(&mut heart_progress.circle_scale.get_images(
+                    all_params,
+                    &[ParamType::U32(all_params_val.heart_progress)],
                     images,
                 ));
 
@@ -628,7 +821,7 @@ impl Preview for Option<HeartProgress> {
                         if let Some(images_count) = line_scale.images_count {
                             res.append(&mut heart_progress.line_scale.get_images(
                                 all_params,
-                                &vec![ParamType::U32(Some(
+                                &[ParamType::U32(Some(
                                     (value as f32 / 100. * (images_count - 1) as f32).round()
                                         as u32,
                                 ))],
@@ -648,8 +841,8 @@ impl Preview for Option<WeekDaysIcons> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -664,10 +857,11 @@ impl Preview for Option<WeekDaysIcons> {
                         4 => &week_days_icons.friday,
                         5 => &week_days_icons.saturday,
                         6 => &week_days_icons.sunday,
-                        _ => unreachable!(),
+                        // Week day from scenario or CLI can be anything
+                        _ => return res,
                     };
 
-                    res.append(&mut day.get_images(all_params, &vec![], images));
+                    res.append(&mut day.get_images(all_params, &[], images));
                 }
             }
         }
@@ -680,23 +874,23 @@ impl Preview for Option<Alarm> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(alarm) = &self {
             if let Some(all_params_val) = &all_params {
                 if all_params_val.alarm_on {
-                    res.append(&mut alarm.on_image.get_images(all_params, &vec![], images));
+                    res.append(&mut alarm.on_image.get_images(all_params, &[], images));
                 } else {
-                    res.append(&mut alarm.off_image.get_images(all_params, &vec![], images));
+                    res.append(&mut alarm.off_image.get_images(all_params, &[], images));
                 }
                 if let Some(alarm_hours) = all_params_val.alarm_hours {
                     if let Some(alarm_minutes) = all_params_val.alarm_minutes {
                         res.append(&mut numbers_with_delimiters_get_images(
                             &alarm.number,
-                            &vec![alarm_hours as f32, alarm_minutes as f32],
+                            &[alarm_hours as f32, alarm_minutes as f32],
                             images,
                             &None,
                             &alarm.delimiter_image_index,
@@ -717,8 +911,8 @@ impl Preview for Option<Status> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -750,8 +944,8 @@ impl Preview for Option<Date> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -773,7 +967,7 @@ impl Preview for Option<Date> {
 
                             res.append(&mut separate.months_en.get_images(
                                 all_params,
-                                &vec![ParamType::U32(Some(value - 1))],
+                                &[ParamType::U32(Some(value - 1))],
                                 images,
                             ));
                         }
@@ -796,7 +990,7 @@ impl Preview for Option<Date> {
                             if let Some(day) = all_params_val.day {
                                 res.append(&mut numbers_with_delimiters_get_images(
                                     &one_line.number,
-                                    &vec![month as f32, day as f32],
+                                    &[month as f32, day as f32],
                                     images,
                                     &None,
                                     &one_line.delimiter_image_index,
@@ -838,7 +1032,7 @@ impl Preview for Option<Date> {
                 if let Some(weekday) = all_params_val.weekday {
                     res.append(&mut date.en_week_days.get_images(
                         all_params,
-                        &vec![ParamType::U32(Some(weekday))],
+                        &[ParamType::U32(Some(weekday))],
                         images,
                     ));
                 }
@@ -853,8 +1047,8 @@ impl Preview for Option<Weather> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -864,7 +1058,7 @@ impl Preview for Option<Weather> {
                     if let Some(value) = all_params_val.weather {
                         res.append(&mut icon.custom_icon.get_images(
                             all_params,
-                            &vec![ParamType::U32(Some(value))],
+                            &[ParamType::U32(Some(value))],
                             images,
                         ));
                     }
@@ -996,8 +1190,8 @@ impl Preview for Option<StepsProgress> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -1005,7 +1199,12 @@ impl Preview for Option<StepsProgress> {
             if let Some(all_params_val) = &all_params {
                 res.append(&mut steps_progress.linear.get_images(
                     all_params,
-                    &vec![ParamType::U32(all_params_val.steps_progress)],
+                    &[ParamType::U32(all_params_val.steps_progress)],
+                    images,
+                ));
+                res.append(&mut steps_progress.circle_scale.get_images(
+                    all_params,
+                    &[ParamType::U32(all_params_val.steps_progress)],
                     images,
                 ));
                 if let Some(value) = all_params_val.steps_progress {
@@ -1013,7 +1212,7 @@ impl Preview for Option<StepsProgress> {
                         if let Some(images_count) = line_scale.images_count {
                             res.append(&mut steps_progress.line_scale.get_images(
                                 all_params,
-                                &vec![ParamType::U32(Some(
+                                &[ParamType::U32(Some(
                                     (value as f32 / 100. * (images_count - 1) as f32).round()
                                         as u32,
                                 ))],
@@ -1033,8 +1232,8 @@ impl Preview for Option<Battery> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
@@ -1060,7 +1259,7 @@ impl Preview for Option<Battery> {
                         if let Some(images_count) = battery_icon.images_count {
                             res.append(&mut battery.battery_icon.get_images(
                                 all_params,
-                                &vec![ParamType::U32(Some(
+                                &[ParamType::U32(Some(
                                     (value as f32 / 100. * (images_count - 1) as f32).round()
                                         as u32,
                                 ))],
@@ -1071,7 +1270,7 @@ impl Preview for Option<Battery> {
                 }
                 res.append(&mut battery.linear.get_images(
                     all_params,
-                    &vec![ParamType::U32(all_params_val.battery)],
+                    &[ParamType::U32(all_params_val.battery)],
                     images,
                 ));
             }
@@ -1085,18 +1284,22 @@ impl Preview for Option<Other> {
     fn get_images(
         &self,
         all_params: &Option<PreviewParams>,
-        _params: &Vec<ParamType>,
-        images: &Vec<Image>,
+        _params: &[ParamType],
+        images: &[Image],
     ) -> Vec<ImageWithCoords> {
         let mut res = vec![];
 
         if let Some(other) = &self {
             if let Some(all_params_val) = &all_params {
-                if let Some(value) = all_params_val.animation {
-                    for animation in &other.animation.0 {
+                for animation in &other.animation.0 {
+                    let value = match all_params_val.animation_time {
+                        Some(time) => Some(animation.frame_at(time)),
+                        None => all_params_val.animation,
+                    };
+                    if let Some(value) = value {
                         res.append(&mut animation.animation_images.get_images(
                             all_params,
-                            &vec![ParamType::U32(Some(value))],
+                            &[ParamType::U32(Some(value))],
                             images,
                         ));
                     }
@@ -1108,19 +1311,275 @@ impl Preview for Option<Other> {
     }
 }
 
+impl Preview for Option<miband4::Activity> {
+    fn get_images(
+        &self,
+        all_params: &Option<PreviewParams>,
+        _params: &[ParamType],
+        images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let Some(activity) = &self {
+            if let Some(all_params_val) = &all_params {
+                if let Some(value) = all_params_val.calories {
+                    res.append(&mut number_get_images(
+                        &activity.calories,
+                        value as f32,
+                        images,
+                        &None,
+                        &None,
+                        &None,
+                        &None,
+                        None,
+                    ));
+                }
+                if let Some(value) = all_params_val.pulse {
+                    res.append(&mut number_get_images(
+                        &activity.pulse,
+                        value as f32,
+                        images,
+                        &None,
+                        &None,
+                        &None,
+                        &None,
+                        None,
+                    ));
+                }
+                res.append(&mut activity.steps.get_images(
+                    all_params,
+                    &[ParamType::U32(all_params_val.steps)],
+                    images,
+                ));
+                res.append(&mut activity.distance.get_images(
+                    all_params,
+                    &[ParamType::F32(all_params_val.distance)],
+                    images,
+                ));
+            }
+        }
+
+        res
+    }
+}
+
+impl Preview for Option<miband4::Date> {
+    fn get_images(
+        &self,
+        all_params: &Option<PreviewParams>,
+        _params: &[ParamType],
+        images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let Some(date) = &self {
+            if let Some(all_params_val) = &all_params {
+                if let Some(month_and_day) = &date.month_and_day {
+                    let month_width = month_and_day.two_digits_month.unwrap_or(false).then_some(2);
+                    let day_width = month_and_day.two_digits_day.unwrap_or(false).then_some(2);
+                    if let Some(separate) = &month_and_day.separate {
+                        if let Some(value) = all_params_val.month {
+                            res.append(&mut number_get_images(
+                                &separate.month,
+                                value as f32,
+                                images,
+                                &None,
+                                &None,
+                                &None,
+                                &None,
+                                month_width,
+                            ));
+                        }
+                        if let Some(value) = all_params_val.day {
+                            res.append(&mut number_get_images(
+                                &separate.day,
+                                value as f32,
+                                images,
+                                &None,
+                                &None,
+                                &None,
+                                &None,
+                                day_width,
+                            ));
+                        }
+                    }
+
+                    if let Some(one_line) = &month_and_day.one_line {
+                        if let Some(month) = all_params_val.month {
+                            if let Some(day) = all_params_val.day {
+                                res.append(&mut numbers_with_delimiters_get_images(
+                                    &one_line.number,
+                                    &[month as f32, day as f32],
+                                    images,
+                                    &None,
+                                    &one_line.delimiter_image_index,
+                                    &None,
+                                    false,
+                                    month_width.or(day_width),
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                if let Some(weekday) = all_params_val.weekday {
+                    res.append(&mut date.week_day.get_images(
+                        all_params,
+                        &[ParamType::U32(Some(weekday))],
+                        images,
+                    ));
+                }
+            }
+        }
+
+        res
+    }
+}
+
+impl Preview for Option<miband4::Status> {
+    fn get_images(
+        &self,
+        all_params: &Option<PreviewParams>,
+        _params: &[ParamType],
+        images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let Some(status) = &self {
+            if let Some(all_params_val) = &all_params {
+                res.append(&mut status_image_get_images(
+                    &status.bluetooth,
+                    all_params_val.bluetooth,
+                    images,
+                ));
+                res.append(&mut status_image_get_images(
+                    &status.alarm,
+                    all_params_val.alarm_on,
+                    images,
+                ));
+                res.append(&mut status_image_get_images(
+                    &status.lock,
+                    all_params_val.lock,
+                    images,
+                ));
+                res.append(&mut status_image_get_images(
+                    &status.do_not_disturb,
+                    all_params_val.do_not_disturb,
+                    images,
+                ));
+            }
+        }
+
+        res
+    }
+}
+
+impl Preview for Option<miband4::Battery> {
+    fn get_images(
+        &self,
+        all_params: &Option<PreviewParams>,
+        _params: &[ParamType],
+        images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let Some(battery) = &self {
+            if let Some(all_params_val) = &all_params {
+                if let Some(value) = all_params_val.battery {
+                    res.append(&mut number_get_images(
+                        &battery.text,
+                        value as f32,
+                        images,
+                        &None,
+                        &None,
+                        &None,
+                        &None,
+                        None,
+                    ));
+
+                    // Icon without images is not drawn
+                    if let Some(last_image) = battery
+                        .icon
+                        .as_ref()
+                        .and_then(|icon| icon.images_count?.checked_sub(1))
+                    {
+                        res.append(&mut battery.icon.get_images(
+                            all_params,
+                            &[ParamType::U32(Some(
+                                (value as f32 / 100. * last_image as f32).round() as u32,
+                            ))],
+                            images,
+                        ));
+                    }
+                }
+            }
+        }
+
+        res
+    }
+}
+
+// Forecast of every day is shown with current weather and temperatures of today
+impl Preview for Option<gtr::Forecast> {
+    fn get_images(
+        &self,
+        all_params: &Option<PreviewParams>,
+        _params: &[ParamType],
+        images: &[Image],
+    ) -> Vec<ImageWithCoords> {
+        let mut res = vec![];
+
+        if let Some(forecast) = &self {
+            if let Some(all_params_val) = &all_params {
+                for day in &forecast.days {
+                    if let Some(value) = all_params_val.weather {
+                        res.append(&mut day.icon.get_images(
+                            all_params,
+                            &[ParamType::U32(Some(value))],
+                            images,
+                        ));
+                    }
+
+                    for (number, value) in [
+                        (&day.day_temperature, all_params_val.day_temperature),
+                        (&day.night_temperature, all_params_val.night_temperature),
+                    ] {
+                        if let Some(value) = value {
+                            res.append(&mut number_get_images(
+                                number,
+                                value as f32,
+                                images,
+                                &None,
+                                &None,
+                                &day.minus_image_index,
+                                &day.degrees_image_index,
+                                None,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        res
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use {crate::common::*, crate::miband::*};
+    use super::*;
 
     #[test]
     fn generate_simple_preview() {
         let watchface = Watchface {
+            header: WatchfaceHeader::default(),
             parameters: Some(MiBandParams {
                 background: Some(Background {
                     image: Some(ImageReference {
                         x: 1,
                         y: 128,
                         image_index: Some(ImgId(0)),
+                        ..Default::default()
                     }),
                     ..Default::default()
                 }),
@@ -1131,6 +1590,7 @@ mod tests {
                 height: 1,
                 ..Default::default()
             }],
+            source: None,
         };
         let preview = watchface.generate_preview(None);
         assert_eq!(
@@ -1143,15 +1603,251 @@ mod tests {
         )
     }
 
+    #[test]
+    fn vector_shape_is_drawn_around_its_bounds() {
+        let vector_shape = Some(VectorShape {
+            color: Some(Color(0xFF, 0x00, 0x00, 0xFF)),
+            center: Some(Coordinates {
+                x: 63,
+                y: 147,
+                ..Default::default()
+            }),
+            shape: vec![
+                Coordinates {
+                    x: 0,
+                    y: -2,
+                    ..Default::default()
+                },
+                Coordinates {
+                    x: 20,
+                    y: -2,
+                    ..Default::default()
+                },
+                Coordinates {
+                    x: 20,
+                    y: 2,
+                    ..Default::default()
+                },
+                Coordinates {
+                    x: 0,
+                    y: 2,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+
+        // 15 of 60 points to the right, so shape is not rotated
+        let preview = vector_shape.get_images(
+            &None,
+            &[ParamType::U32(Some(15)), ParamType::F32(Some(60.))],
+            &[],
+        );
+        assert_eq!(preview.len(), 1);
+        assert_eq!((preview[0].x, preview[0].y), (62, 144));
+        let ImageType::Image(image) = &preview[0].image_type else {
+            panic!("drawn image expected");
+        };
+        assert_eq!((image.width, image.height), (22, 6));
+        let pixel = |x: usize, y: usize| &image.pixels[(y * 22 + x) * 4..(y * 22 + x) * 4 + 4];
+        assert_eq!(pixel(10, 3), &[0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(0, 0), &[0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn circle_scale_points() {
+        let circle_scale = CircleScale {
+            center_x: Some(63),
+            center_y: Some(147),
+            radius_x: Some(50),
+            ..Default::default()
+        };
+
+        let point = |angle| {
+            let (x, y) = circle_scale_point(&circle_scale, angle).unwrap();
+            (x.round() as i32, y.round() as i32)
+        };
+        assert_eq!(point(0.), (63, 97));
+        assert_eq!(point(90.), (113, 147));
+        assert_eq!(point(180.), (63, 197));
+        assert_eq!(point(270.), (13, 147));
+    }
+
+    #[test]
+    fn generate_preview_with_circle_scale() {
+        let watchface = Watchface {
+            header: WatchfaceHeader::default(),
+            parameters: Some(MiBandParams {
+                steps_progress: Some(StepsProgress {
+                    circle_scale: Some(CircleScale {
+                        center_x: Some(63),
+                        center_y: Some(147),
+                        radius_x: Some(50),
+                        radius_y: Some(50),
+                        start_angle: Some(0),
+                        end_angle: Some(360),
+                        width: Some(4),
+                        color: Some(Color(0xFF, 0x00, 0x00, 0xFF)),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            images: vec![],
+            source: None,
+        };
+
+        let preview = watchface.generate_preview(Some(PreviewParams {
+            steps_progress: Some(50),
+            ..Default::default()
+        }));
+        assert_eq!(preview.len(), 1);
+        let ImageType::Image(image) = &preview[0].image_type else {
+            panic!("drawn image expected");
+        };
+        let alpha = |x: i32, y: i32| {
+            let x = x - preview[0].x;
+            let y = y - preview[0].y;
+            if x < 0 || y < 0 || x >= image.width as i32 || y >= image.height as i32 {
+                return 0x00;
+            }
+            image.pixels[(y as usize * image.width as usize + x as usize) * 4 + 3]
+        };
+
+        // Arc starts at 12 o'clock and goes clockwise to 6 o'clock
+        assert_eq!(alpha(64, 97), 0xFF);
+        assert_eq!(alpha(61, 97), 0x00);
+        assert_eq!(alpha(112, 147), 0xFF);
+        assert_eq!(alpha(63, 197), 0xFF);
+        assert_eq!(alpha(61, 197), 0x00);
+        assert_eq!(alpha(14, 147), 0x00);
+    }
+
+    #[test]
+    fn circle_scale_with_zero_width_and_huge_angles() {
+        let circle_scale = |width| CircleScale {
+            center_x: Some(63),
+            center_y: Some(147),
+            radius_x: Some(50),
+            start_angle: Some(0),
+            end_angle: Some(u32::MAX),
+            width: Some(width),
+            color: Some(Color(0xFF, 0x00, 0x00, 0xFF)),
+            ..Default::default()
+        };
+        let params = [ParamType::U32(Some(100))];
+
+        assert!(Some(circle_scale(0))
+            .get_images(&None, &params, &[])
+            .is_empty());
+        // One line per degree of a full circle
+        assert_eq!(circle_scale_arc(&circle_scale(4), 100).unwrap().len(), 361);
+        assert_eq!(
+            Some(circle_scale(4)).get_images(&None, &params, &[]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn skip_shapes_out_of_drawn_area() {
+        let circle_scale = |radius, width| {
+            Some(CircleScale {
+                center_x: Some(63),
+                center_y: Some(147),
+                radius_x: Some(radius),
+                start_angle: Some(0),
+                end_angle: Some(360),
+                width: Some(width),
+                color: Some(Color(0xFF, 0x00, 0x00, 0xFF)),
+                ..Default::default()
+            })
+        };
+        let params = [ParamType::U32(Some(100))];
+
+        for (radius, width) in [(u32::MAX, 4), (50, u32::MAX), (100_000, 4), (2000, 4)] {
+            let images = circle_scale(radius, width).get_images(&None, &params, &[]);
+            for image in images {
+                let ImageType::Image(image) = image.image_type else {
+                    panic!("shape is not drawn as an image");
+                };
+                assert!(image.width as f32 <= MAX_DRAW_SIZE);
+                assert!(image.height as f32 <= MAX_DRAW_SIZE);
+            }
+        }
+    }
+
+    #[test]
+    fn skip_icon_of_wrong_week_day() {
+        let week_days_icons = Some(WeekDaysIcons {
+            monday: Some(ImageReference {
+                image_index: Some(ImgId(0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        for (weekday, images_count) in [(0, 1), (7, 0)] {
+            let params = Some(PreviewParams {
+                weekday: Some(weekday),
+                ..Default::default()
+            });
+            assert_eq!(
+                week_days_icons.get_images(&params, &[], &[]).len(),
+                images_count
+            );
+        }
+    }
+
+    #[test]
+    fn render_preview_with_alpha_and_negative_coordinates() {
+        let watchface = Watchface {
+            header: WatchfaceHeader::default(),
+            parameters: Some(MiBandParams {
+                background: Some(Background {
+                    image: Some(ImageReference {
+                        x: -1,
+                        y: -1,
+                        image_index: Some(ImgId(0)),
+                        ..Default::default()
+                    }),
+                    color: Some(Color(0x00, 0x00, 0xFF, 0xFF)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            images: vec![Image {
+                pixels: vec![
+                    0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF, // first row
+                    0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0x80, // second row
+                ],
+                width: 2,
+                height: 2,
+                bits_per_pixel: 32,
+                pixel_format: 0x10,
+            }],
+            source: None,
+        };
+
+        let preview = watchface.render_preview(&PreviewParams::default());
+        assert_eq!(preview.dimensions(), (126, 294));
+        // Only the bottom right pixel is on the screen, it is blended with background color
+        assert_eq!(preview.get_pixel(0, 0).0, [0x00, 0x80, 0x7F, 0xFF]);
+        assert_eq!(preview.get_pixel(1, 0).0, [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(preview.get_pixel(0, 1).0, [0x00, 0x00, 0xFF, 0xFF]);
+    }
+
     #[test]
     fn generate_preview_with_time() {
         let watchface = Watchface {
+            header: WatchfaceHeader::default(),
             parameters: Some(MiBandParams {
                 background: Some(Background {
                     image: Some(ImageReference {
                         x: 1,
                         y: 258,
                         image_index: Some(ImgId(0)),
+                        ..Default::default()
                     }),
                     ..Default::default()
                 }),
@@ -1162,13 +1858,16 @@ mod tests {
                             y: 20,
                             image_index: Some(ImgId(1)),
                             images_count: Some(10),
+                            ..Default::default()
                         }),
                         ones: Some(ImageRange {
                             x: 15,
                             y: 20,
                             image_index: Some(ImgId(1)),
                             images_count: Some(10),
+                            ..Default::default()
                         }),
+                        ..Default::default()
                     }),
                     minutes: Some(TimeNumbers {
                         tens: Some(ImageRange {
@@ -1176,13 +1875,16 @@ mod tests {
                             y: 40,
                             image_index: Some(ImgId(1)),
                             images_count: Some(10),
+                            ..Default::default()
                         }),
                         ones: Some(ImageRange {
                             x: 15,
                             y: 40,
                             image_index: Some(ImgId(1)),
                             images_count: Some(10),
+                            ..Default::default()
                         }),
+                        ..Default::default()
                     }),
                     ..Default::default()
                 }),
@@ -1245,6 +1947,7 @@ mod tests {
                     ..Default::default()
                 },
             ],
+            source: None,
         };
         let preview = watchface.generate_preview(Some(PreviewParams {
             hours: Some(11),
@@ -1286,12 +1989,14 @@ mod tests {
     #[test]
     fn generate_preview_with_steps() {
         let watchface = Watchface {
+            header: WatchfaceHeader::default(),
             parameters: Some(MiBandParams {
                 background: Some(Background {
                     image: Some(ImageReference {
                         x: 1,
                         y: 39,
                         image_index: Some(ImgId(0)),
+                        ..Default::default()
                     }),
                     ..Default::default()
                 }),
@@ -1307,6 +2012,7 @@ mod tests {
                             spacing_y: 0,
                             image_index: Some(ImgId(1)),
                             images_count: Some(10),
+                            ..Default::default()
                         }),
                         ..Default::default()
                     }),
@@ -1371,6 +2077,7 @@ mod tests {
                     ..Default::default()
                 },
             ],
+            source: None,
         };
         let preview = watchface.generate_preview(Some(PreviewParams {
             steps: Some(1284),
@@ -1411,12 +2118,14 @@ mod tests {
     #[test]
     fn generate_preview_with_top_center_alignment() {
         let watchface = Watchface {
+            header: WatchfaceHeader::default(),
             parameters: Some(MiBandParams {
                 background: Some(Background {
                     image: Some(ImageReference {
                         x: 16,
                         y: 79,
                         image_index: Some(ImgId(0)),
+                        ..Default::default()
                     }),
                     ..Default::default()
                 }),
@@ -1432,6 +2141,7 @@ mod tests {
                             spacing_y: 0,
                             image_index: Some(ImgId(1)),
                             images_count: Some(10),
+                            ..Default::default()
                         }),
                         ..Default::default()
                     }),
@@ -1496,6 +2206,7 @@ mod tests {
                     ..Default::default()
                 },
             ],
+            source: None,
         };
         let preview = watchface.generate_preview(Some(PreviewParams {
             steps: Some(1284),
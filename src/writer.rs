@@ -0,0 +1,1351 @@
+use crate::{
+    common::*, error::WatchfaceError, parser::write_variable_width_value, preview::Preview,
+}; // TODO: not use star
+
+pub fn image_write(image: &Image) -> Result<Vec<u8>, WatchfaceError> {
+    image_write_with_format(image, image.pixel_format, image.bits_per_pixel)
+}
+
+// Encodes image in the smallest format which keeps all pixels as they are
+pub fn image_encode(image: &Image) -> Result<Vec<u8>, WatchfaceError> {
+    let (pixel_format, bits_per_pixel) = smallest_image_format(image);
+    image_write_with_format(image, pixel_format, bits_per_pixel)
+}
+
+// Compressed format is not selected, band support of it is not checked yet
+pub fn smallest_image_format(image: &Image) -> (u16, u16) {
+    let pixels = image.pixels.chunks(4).collect::<Vec<_>>();
+    let pixels_count = pixels.len();
+    let opaque = pixels.iter().all(|p| p[3] == 0xFF);
+    let rgb565 = pixels
+        .iter()
+        .all(|p| p[0] & 0x07 == 0 && p[1] & 0x03 == 0 && p[2] & 0x07 == 0);
+    let rgba4444 = pixels
+        .iter()
+        .all(|p| p[0] & 0x0F == 0 && p[1] & 0x0F == 0 && p[2] & 0x0F == 0 && p[3] & 0x0F == 0x0F);
+
+    // (pixel format, bits per pixel, size of palette and pixels)
+    let mut formats = vec![];
+
+    if let Some(colors_count) = palette_colors_count(&pixels) {
+        for bits_per_pixel in [1, 2, 4, 8] {
+            if colors_count <= 1 << bits_per_pixel {
+                let row_size = (bits_per_pixel * image.width as usize).div_ceil(8);
+                formats.push((
+                    0x64,
+                    bits_per_pixel as u16,
+                    4 * colors_count + row_size * image.height as usize,
+                ));
+            }
+        }
+    }
+    if opaque && rgb565 {
+        formats.push((0x1C, 16, 2 * pixels_count));
+    }
+    if rgba4444 {
+        formats.push((0x13, 16, 2 * pixels_count));
+    }
+    if rgb565 {
+        formats.push((0x1C, 24, 3 * pixels_count));
+    }
+    formats.push((0x10, 32, 4 * pixels_count));
+
+    let (pixel_format, bits_per_pixel, _) = formats
+        .into_iter()
+        .min_by_key(|(_, _, size)| *size)
+        .unwrap();
+    (pixel_format, bits_per_pixel)
+}
+
+// Palette has only opaque colors and a single transparent one
+fn palette_colors_count(pixels: &[&[u8]]) -> Option<usize> {
+    let mut colors = vec![];
+    let mut transparent = None;
+    for p in pixels {
+        match p[3] {
+            0xFF => {
+                if !colors.contains(&&p[0..3]) {
+                    colors.push(&p[0..3]);
+                }
+            }
+            0x00 => match transparent {
+                None => transparent = Some(&p[0..3]),
+                Some(color) if color == &p[0..3] => (),
+                Some(_) => return None,
+            },
+            _ => return None,
+        }
+        if colors.len() > 256 {
+            return None;
+        }
+    }
+
+    let colors_count = colors.len() + transparent.iter().count();
+    if colors_count == 0 || colors_count > 256 {
+        return None;
+    }
+    Some(colors_count)
+}
+
+pub fn image_write_with_format(
+    image: &Image,
+    pixel_format: u16,
+    bits_per_pixel: u16,
+) -> Result<Vec<u8>, WatchfaceError> {
+    if pixel_format == 0x65 {
+        return Ok(compressed_image_write(image));
+    }
+    if pixel_format == 0xFFFF {
+        return Ok(bit32_image_write(image));
+    }
+
+    let width = image.width as usize;
+    let height = image.height as usize;
+    let row_size = ((bits_per_pixel as usize * width) as f32 / 8.).ceil() as u16;
+
+    // Alpha is inverted, 0xFF is transparent
+    let pixel = |x: usize, y: usize| {
+        let position = (y * width + x) * 4;
+        let p = &image.pixels[position..position + 4];
+        (p[0], p[1], p[2], 0xFF - p[3])
+    };
+
+    let mut palette: Vec<(u8, u8, u8)> = vec![];
+    let mut transparent_palette_color = 0u16;
+    let mut color_ids = vec![];
+    if pixel_format == 0x64 {
+        // Palette can have only opaque colors and a single transparent one
+        for y in 0..height {
+            for x in 0..width {
+                let (red, green, blue, alpha) = pixel(x, y);
+                let transparent = alpha > 0x7F;
+                let color_id = if transparent && transparent_palette_color != 0 {
+                    transparent_palette_color - 1
+                } else if let Some(color_id) = (0..palette.len()).find(|color_id| {
+                    palette[*color_id] == (red, green, blue)
+                        && *color_id as u16 + 1 != transparent_palette_color
+                }) {
+                    color_id as u16
+                } else {
+                    palette.push((red, green, blue));
+                    if transparent {
+                        transparent_palette_color = palette.len() as u16;
+                    }
+                    palette.len() as u16 - 1
+                };
+                color_ids.push(color_id as u8);
+            }
+        }
+        if palette.len() > 1 << bits_per_pixel {
+            return Err(WatchfaceError::TooManyColors {
+                colors_count: palette.len(),
+                bits_per_pixel,
+            });
+        }
+    }
+
+    let mut res = vec![];
+    res.extend(0x4D42u16.to_le_bytes());
+    res.extend(pixel_format.to_le_bytes());
+    res.extend(image.width.to_le_bytes());
+    res.extend(image.height.to_le_bytes());
+    res.extend(row_size.to_le_bytes());
+    res.extend(bits_per_pixel.to_le_bytes());
+    res.extend((palette.len() as u16).to_le_bytes());
+    res.extend(transparent_palette_color.to_le_bytes());
+
+    for (red, green, blue) in &palette {
+        res.extend([*red, *green, *blue, 0x00]);
+    }
+
+    for y in 0..height {
+        if pixel_format == 0x64 {
+            let pixels_per_byte = 8 / bits_per_pixel as usize;
+            for byte_colors in color_ids[y * width..(y + 1) * width].chunks(pixels_per_byte) {
+                let mut byte = 0u8;
+                for (i, color_id) in byte_colors.iter().enumerate() {
+                    let bit_position = 8 - (i + 1) * bits_per_pixel as usize;
+                    byte |= color_id << bit_position;
+                }
+                res.push(byte);
+            }
+            continue;
+        }
+
+        for x in 0..width {
+            let (red, green, blue, alpha) = pixel(x, y);
+            if bits_per_pixel == 32 {
+                res.extend([red, green, blue, alpha]);
+                continue;
+            }
+
+            let rgba = if pixel_format == 0x13 {
+                // color is 16 bit (4:4:4:4) abgr
+                (alpha as u16 & 0xF0) << 8
+                    | (blue as u16 & 0xF0) << 4
+                    | green as u16 & 0xF0
+                    | (red as u16) >> 4
+            } else if pixel_format == 0x1C || pixel_format == 0x09 {
+                // color is 16bit (5:6:5) rgb
+                (red as u16 & 0xF8) << 8 | (green as u16 & 0xFC) << 3 | (blue as u16) >> 3
+            } else {
+                // color is 16bit (5:6:5) bgr
+                (blue as u16 & 0xF8) << 8 | (green as u16 & 0xFC) << 3 | (red as u16) >> 3
+            };
+
+            if bits_per_pixel == 24 {
+                // 24 bits is 16 bit color data (big endian) with 8 bit alpha
+                res.push(alpha);
+                res.extend(rgba.to_be_bytes());
+            } else {
+                // for the 16 bit images, the value is little endian
+                res.extend(rgba.to_le_bytes());
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+pub fn compressed_image_write(image: &Image) -> Vec<u8> {
+    // Alpha is inverted, 0xFF is transparent
+    let pixels = image
+        .pixels
+        .chunks(4)
+        .map(|p| [p[0], p[1], p[2], 0xFF - p[3]])
+        .collect::<Vec<_>>();
+
+    let mut data = vec![];
+    let mut literal: Vec<[u8; 4]> = vec![];
+    let mut i = 0;
+    while i < pixels.len() {
+        let mut count = 1;
+        while i + count < pixels.len() && pixels[i + count] == pixels[i] && count < 128 {
+            count += 1;
+        }
+
+        if count > 1 {
+            if !literal.is_empty() {
+                data.push(literal.len() as u8 - 1);
+                data.extend(literal.concat());
+                literal.clear();
+            }
+            data.push(0x80 | (count as u8 - 1));
+            data.extend(pixels[i]);
+        } else {
+            literal.push(pixels[i]);
+            if literal.len() == 128 {
+                data.push(literal.len() as u8 - 1);
+                data.extend(literal.concat());
+                literal.clear();
+            }
+        }
+        i += count;
+    }
+    if !literal.is_empty() {
+        data.push(literal.len() as u8 - 1);
+        data.extend(literal.concat());
+    }
+
+    let mut res = vec![];
+    res.extend(0x4D42u16.to_le_bytes());
+    res.extend(0x65u16.to_le_bytes());
+    res.extend(image.width.to_le_bytes());
+    res.extend(image.height.to_le_bytes());
+    res.extend(32u16.to_le_bytes());
+    res.extend((data.len() as u32).to_le_bytes());
+    res.append(&mut data);
+    res
+}
+
+pub fn bit32_image_write(image: &Image) -> Vec<u8> {
+    let mut res = vec![];
+    res.extend(0x4D42u16.to_le_bytes());
+    res.extend(0xFFFFu16.to_le_bytes());
+    res.extend(image.width.to_le_bytes());
+    res.extend(image.height.to_le_bytes());
+    res.extend(((image.width as u32 * 4) as u16).to_le_bytes());
+    res.extend(32u16.to_le_bytes());
+    // Pixels are BGRA with not inverted alpha
+    for p in image.pixels.chunks(4) {
+        res.extend([p[2], p[1], p[0], p[3]]);
+    }
+    res
+}
+
+pub fn params_writer(params: &Params, bits: u32) -> Vec<u8> {
+    let mut res = vec![];
+    for (key, value) in params.ordered() {
+        res.append(&mut param_writer(key, value, bits));
+    }
+    res
+}
+
+pub fn param_writer(key: u8, param: &Param, bits: u32) -> Vec<u8> {
+    let mut res = vec![];
+    let key = (key as i64) << 3;
+    match param {
+        Param::Number(number) => {
+            res.append(&mut write_variable_width_value(key, bits));
+            res.append(&mut write_variable_width_value(*number, bits));
+        }
+        Param::Float(float) => {
+            res.append(&mut write_variable_width_value(key | 0x05, bits));
+            res.extend(float.to_le_bytes());
+        }
+        Param::Child(child) => {
+            // Child is written with its size as a value
+            let mut child = params_writer(child, bits);
+            res.append(&mut write_variable_width_value(key | 0x02, bits));
+            res.append(&mut write_variable_width_value(child.len() as i64, bits));
+            res.append(&mut child);
+        }
+    }
+    res
+}
+
+pub fn header_writer(header: &WatchfaceHeader) -> Vec<u8> {
+    let mut raw = header.raw;
+    raw[0..2].copy_from_slice(&header.version.to_le_bytes());
+    raw[7] = header.format_id;
+    raw[8..12].copy_from_slice(&header.unknown8.to_le_bytes());
+    raw[12..14].copy_from_slice(&header.device_id.to_le_bytes());
+    raw[14..18].copy_from_slice(&header.watchface_id.to_le_bytes());
+    raw[18..22].copy_from_slice(&header.unknown18.to_le_bytes());
+    [&header.signature[..], &raw[..]].concat()
+}
+
+pub fn dial_header_writer(header: &DialHeader) -> Vec<u8> {
+    [&DIAL_SIGNATURE[..], &header.raw[..]].concat()
+}
+
+pub fn bin_writer<T>(watchface: &Watchface<T>) -> Result<Vec<u8>, WatchfaceError>
+where
+    T: WatchfaceParams,
+    Option<T>: Transform + Preview,
+{
+    let mut untransformed = watchface.parameters.untransform();
+    let source = watchface.source.as_ref();
+
+    // Not edited images are kept as they were encoded, palette order and compression can differ
+    let images = watchface
+        .images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| {
+            source
+                .filter(|source| source.images.get(index) == Some(image))
+                .and_then(|source| source.encoded_images.get(index))
+        })
+        .collect::<Vec<_>>();
+    let images_edited = !source.is_some_and(|source| {
+        source.encoded_images.len() == images.len() && images.iter().all(Option::is_some)
+    });
+
+    let mut res = vec![];
+    res.append(&mut watchface.header.write());
+
+    // Images count is a part of parameters info, so it is written again with edited images
+    match source {
+        Some(source) if !images_edited && source.parameters == untransformed => {
+            res.extend(&source.parameters_bytes);
+        }
+        _ => {
+            let all_params = match untransformed.pop() {
+                Some(Param::Child(child)) => child,
+                _ => Params::new(),
+            };
+            res.append(&mut parameters_writer(
+                &all_params,
+                watchface.images.len(),
+                T::VALUE_BITS,
+                source,
+            ));
+        }
+    }
+
+    match source {
+        Some(source) if !images_edited => res.extend(&source.images_bytes),
+        _ => {
+            let mut images_info = vec![];
+            let mut images_data = vec![];
+            for (image, bytes) in watchface.images.iter().zip(images) {
+                images_info.extend((images_data.len() as u32).to_le_bytes());
+                match bytes {
+                    Some(bytes) => images_data.extend(bytes),
+                    None => images_data.append(&mut image_write(image)?),
+                }
+            }
+            res.append(&mut images_info);
+            res.append(&mut images_data);
+        }
+    }
+    Ok(res)
+}
+
+// Writes buffer size, parameters info and parameters
+fn parameters_writer(
+    all_params: &Params,
+    images_count: usize,
+    bits: u32,
+    source: Option<&WatchfaceSource>,
+) -> Vec<u8> {
+    let source_params = match source.and_then(|source| source.parameters.last()) {
+        Some(Param::Child(child)) => Some(child),
+        _ => None,
+    };
+
+    // Every section gets its own block, parameter info stores offset and size of it
+    let mut sections = vec![];
+    let mut parameters = vec![];
+    let mut buffer_size = 0;
+    for (key, values) in all_params.iter() {
+        let offset = parameters.len();
+        // Not edited section is copied, so order and width of values in it are kept
+        let source_bytes = source
+            .filter(|_| source_params.and_then(|params| params.get(key)) == Some(values))
+            .and_then(|source| source.sections.iter().find(|(section, _)| section == key));
+        match source_bytes {
+            Some((_, bytes)) => parameters.extend(bytes),
+            None => {
+                for value in values {
+                    if let Param::Child(child) = value {
+                        parameters.append(&mut params_writer(child, bits));
+                    }
+                }
+            }
+        }
+        let size = parameters.len() - offset;
+        buffer_size = buffer_size.max(size);
+        sections.push((*key, offset, size));
+    }
+
+    // First parameter info contains parameters size and images count
+    let mut parameter_info = Params::from([(
+        1,
+        vec![Param::Child(Params::from([
+            (1, vec![Param::Number(parameters.len() as i64)]),
+            (2, vec![Param::Number(images_count as i64)]),
+        ]))],
+    )]);
+    for (key, offset, size) in sections {
+        parameter_info.insert(
+            key,
+            vec![Param::Child(Params::from([
+                (1, vec![Param::Number(offset as i64)]),
+                (2, vec![Param::Number(size as i64)]),
+            ]))],
+        );
+    }
+    let parameter_info = params_writer(&parameter_info, bits);
+
+    let mut res = vec![];
+    res.extend((buffer_size as u32).to_le_bytes());
+    res.extend((parameter_info.len() as u32).to_le_bytes());
+    res.extend(parameter_info);
+    res.append(&mut parameters);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{miband::*, parser::*},
+        winnow::stream::{Located, Location},
+    };
+
+    fn write_and_parse_image(image: &Image) -> Image {
+        let bytes = image_write(image).unwrap();
+        image_parse(&mut Located::new(&bytes[..])).unwrap()
+    }
+
+    #[test]
+    fn write_keys_and_values() {
+        let params = Params::from([
+            (1, vec![Param::Number(0x04)]),
+            (2, vec![Param::Number(0x6B)]),
+        ]);
+
+        assert_eq!(params_writer(&params, 64), vec![0x08, 0x04, 0x10, 0x6B]);
+    }
+
+    #[test]
+    fn write_nested_structure() {
+        let params = Params::from([(
+            1,
+            vec![Param::Child(Params::from([
+                (1, vec![Param::Number(0x023C)]),
+                (2, vec![Param::Number(0x6B)]),
+            ]))],
+        )]);
+
+        assert_eq!(
+            params_writer(&params, 64),
+            vec![0x0A, 0x05, 0x08, 0xBC, 0x04, 0x10, 0x6B]
+        );
+    }
+
+    #[test]
+    fn write_lists() {
+        let params = Params::from([
+            (1, vec![Param::Number(0x04), Param::Number(0x7F)]),
+            (2, vec![Param::Number(0x6B)]),
+        ]);
+
+        assert_eq!(
+            params_writer(&params, 64),
+            vec![0x08, 0x04, 0x08, 0x7F, 0x10, 0x6B]
+        );
+    }
+
+    #[test]
+    fn write_keys_in_params_order() {
+        let params = Params::from([
+            (2, vec![Param::Number(0x6B)]),
+            (1, vec![Param::Number(0x04), Param::Number(0x7F)]),
+        ]);
+
+        assert_eq!(
+            params_writer(&params, 64),
+            vec![0x10, 0x6B, 0x08, 0x04, 0x08, 0x7F]
+        );
+    }
+
+    #[test]
+    fn write_multi_byte_id() {
+        let params = Params::from([(32, vec![Param::Number(0x04)])]);
+
+        assert_eq!(params_writer(&params, 64), vec![0x80, 0x02, 0x04]);
+    }
+
+    #[test]
+    fn write_float_values() {
+        let params = Params::from([(
+            1,
+            vec![Param::Child(Params::from([
+                (1, vec![Param::Float(1.25)]),
+                (7, vec![Param::Float(360.0)]),
+            ]))],
+        )]);
+
+        assert_eq!(
+            params_writer(&params, 64),
+            vec![0x0A, 0x0A, 0x0D, 0x00, 0x00, 0xA0, 0x3F, 0x3D, 0x00, 0x00, 0xB4, 0x43]
+        );
+    }
+
+    #[test]
+    fn write_32_bit_image() {
+        let image = Image {
+            pixels: vec![0x11, 0x21, 0x31, 0xBE, 0x12, 0x22, 0x32, 0xBD],
+            width: 2,
+            height: 1,
+            bits_per_pixel: 32,
+            pixel_format: 0x10,
+        };
+
+        assert_eq!(
+            image_write(&image).unwrap(),
+            vec![
+                0x42, 0x4D, 0x10, 0x00, 0x02, 0x00, 0x01, 0x00, 0x08, 0x00, 0x20, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x11, 0x21, 0x31, 0x41, 0x12, 0x22, 0x32, 0x42,
+            ]
+        );
+    }
+
+    #[test]
+    fn write_palette_images() {
+        for bits_per_pixel in [1, 2, 4, 8] {
+            let colors_count = 1usize << bits_per_pixel.min(4);
+            let mut pixels = vec![];
+            for y in 0..3 {
+                for x in 0..5 {
+                    let color_id = ((x + y * 5) % colors_count) as u8;
+                    if color_id == 1 {
+                        pixels.extend([0x10, 0x20, 0x30, 0x00]);
+                    } else {
+                        pixels.extend([color_id * 15, 0x80, 0xFF - color_id, 0xFF]);
+                    }
+                }
+            }
+            let image = Image {
+                pixels,
+                width: 5,
+                height: 3,
+                bits_per_pixel,
+                pixel_format: 0x64,
+            };
+
+            assert_eq!(write_and_parse_image(&image), image);
+        }
+    }
+
+    #[test]
+    fn write_16_bit_images() {
+        for pixel_format in [0x08, 0x09, 0x1B, 0x1C] {
+            let image = Image {
+                pixels: vec![
+                    0xF8, 0xFC, 0xF8, 0xFF, 0x08, 0x04, 0x00, 0xFF, 0x00, 0x00, 0x08, 0xFF,
+                ],
+                width: 3,
+                height: 1,
+                bits_per_pixel: 16,
+                pixel_format,
+            };
+
+            assert_eq!(write_and_parse_image(&image), image);
+        }
+    }
+
+    #[test]
+    fn write_16_bit_image_with_alpha() {
+        let image = Image {
+            pixels: vec![0xF0, 0x10, 0x20, 0xFF, 0x30, 0x40, 0x50, 0x0F],
+            width: 2,
+            height: 1,
+            bits_per_pixel: 16,
+            pixel_format: 0x13,
+        };
+
+        assert_eq!(write_and_parse_image(&image), image);
+    }
+
+    #[test]
+    fn write_24_bit_image() {
+        let image = Image {
+            pixels: vec![0xF8, 0xFC, 0xF8, 0x12, 0x08, 0x04, 0x00, 0xFF],
+            width: 1,
+            height: 2,
+            bits_per_pixel: 24,
+            pixel_format: 0x1C,
+        };
+
+        assert_eq!(write_and_parse_image(&image), image);
+    }
+
+    #[test]
+    fn write_compressed_image() {
+        let image = Image {
+            pixels: vec![
+                0x11, 0x21, 0x31, 0xFF, 0x11, 0x21, 0x31, 0xFF, 0x11, 0x21, 0x31,
+                0xFF, // 1st row
+                0x11, 0x21, 0x31, 0xFF, 0x12, 0x22, 0x32, 0x00, 0x13, 0x23, 0x33,
+                0x7F, // 2nd row
+            ],
+            width: 3,
+            height: 2,
+            bits_per_pixel: 32,
+            pixel_format: 0x65,
+        };
+
+        assert_eq!(
+            image_write(&image).unwrap(),
+            vec![
+                0x42, 0x4D, 0x65, 0x00, 0x03, 0x00, 0x02, 0x00, 0x20, 0x00, 0x0E, 0x00, 0x00, 0x00,
+                0x83, 0x11, 0x21, 0x31, 0x00, 0x01, 0x12, 0x22, 0x32, 0xFF, 0x13, 0x23, 0x33, 0x80,
+            ]
+        );
+    }
+
+    #[test]
+    fn write_long_compressed_image() {
+        let mut pixels = vec![];
+        for x in 0..300u32 {
+            let color = if x < 140 { 0 } else { x as u8 };
+            pixels.extend([color, 0x20, 0x30, 0xFF]);
+        }
+        let image = Image {
+            pixels,
+            width: 300,
+            height: 1,
+            bits_per_pixel: 32,
+            pixel_format: 0x65,
+        };
+
+        assert_eq!(write_and_parse_image(&image), image);
+    }
+
+    #[test]
+    fn write_32_bit_variant_image() {
+        let image = Image {
+            pixels: vec![0x11, 0x21, 0x31, 0xFF, 0x12, 0x22, 0x32, 0x40],
+            width: 2,
+            height: 1,
+            bits_per_pixel: 32,
+            pixel_format: 0xFFFF,
+        };
+
+        assert_eq!(
+            image_write(&image).unwrap(),
+            vec![
+                0x42, 0x4D, 0xFF, 0xFF, 0x02, 0x00, 0x01, 0x00, 0x08, 0x00, 0x20, 0x00, 0x31, 0x21,
+                0x11, 0xFF, 0x32, 0x22, 0x12, 0x40,
+            ]
+        );
+        assert_eq!(write_and_parse_image(&image), image);
+    }
+
+    fn encode_and_parse_image(image: &Image) -> Image {
+        let bytes = image_encode(image).unwrap();
+        image_parse(&mut Located::new(&bytes[..])).unwrap()
+    }
+
+    fn rgba_image(width: u16, height: u16, pixel: impl Fn(u16, u16) -> [u8; 4]) -> Image {
+        let mut pixels = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend(pixel(x, y));
+            }
+        }
+        Image {
+            pixels,
+            width,
+            height,
+            bits_per_pixel: 32,
+            pixel_format: 0x10,
+        }
+    }
+
+    #[test]
+    fn encode_images_losslessly() {
+        let images = [
+            // two colors
+            rgba_image(9, 3, |x, y| {
+                if (x + y) % 2 == 0 {
+                    [0x12, 0x34, 0x56, 0xFF]
+                } else {
+                    [0x00, 0x00, 0x00, 0xFF]
+                }
+            }),
+            // few colors with transparency
+            rgba_image(7, 5, |x, y| match (x * y) % 4 {
+                0 => [0x10, 0x20, 0x30, 0x00],
+                v => [v as u8, 0x80, 0x90, 0xFF],
+            }),
+            // 200 colors
+            rgba_image(40, 40, |x, y| {
+                [((x + y * 40) % 200) as u8, 0x01, 0x02, 0xFF]
+            }),
+            // 16 bit colors
+            rgba_image(40, 10, |x, y| [(x * 8) as u8, (y * 4) as u8, 0xF8, 0xFF]),
+            // 16 bit colors with 4 bit alpha
+            rgba_image(40, 10, |x, y| [(x * 16) as u8, (y * 16) as u8, 0xF0, 0x3F]),
+            // 16 bit colors with alpha
+            rgba_image(40, 10, |x, y| [(x * 8) as u8, (y * 4) as u8, 0xF8, x as u8]),
+            // true colors
+            rgba_image(40, 10, |x, y| [x as u8, y as u8, 0x33, (x * y) as u8]),
+        ];
+        let expected_formats = [
+            (0x64, 1),
+            (0x64, 2),
+            (0x64, 8),
+            (0x1C, 16),
+            (0x13, 16),
+            (0x1C, 24),
+            (0x10, 32),
+        ];
+
+        for (image, expected_format) in images.iter().zip(expected_formats) {
+            let result = encode_and_parse_image(image);
+            assert_eq!(
+                (result.pixel_format, result.bits_per_pixel),
+                expected_format
+            );
+            assert_eq!(result.width, image.width);
+            assert_eq!(result.height, image.height);
+            assert_eq!(result.pixels, image.pixels);
+        }
+    }
+
+    #[test]
+    fn encode_transparent_palette_with_different_colors() {
+        // Palette can have only one transparent color
+        let image = rgba_image(2, 1, |x, _| [x as u8, 0x00, 0x00, 0x00]);
+
+        let result = encode_and_parse_image(&image);
+        assert_eq!(result.pixel_format, 0x10);
+        assert_eq!(result.pixels, image.pixels);
+    }
+
+    #[test]
+    fn write_header() {
+        let header = WatchfaceHeader {
+            version: 2,
+            unknown8: 0x12345678,
+            watchface_id: 65281,
+            ..Default::default()
+        };
+
+        let bytes = header_writer(&header);
+        assert_eq!(&bytes[..4], b"UIHH");
+        assert_eq!(&bytes[4..6], &[0x02, 0x00]);
+        assert_eq!(&bytes[12..16], &[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(&bytes[18..22], &[0x01, 0xFF, 0x00, 0x00]);
+        assert_eq!(&bytes[26..], &DEFAULT_HEADER[22..]);
+        assert_eq!(
+            header_parser(&mut Located::new(&bytes[..])),
+            Ok(WatchfaceHeader {
+                raw: bytes[4..].try_into().unwrap(),
+                ..header
+            })
+        );
+    }
+
+    #[test]
+    fn write_simple_bin() {
+        let watchface = Watchface {
+            header: WatchfaceHeader::default(),
+            parameters: Some(MiBandParams {
+                background: Some(Background {
+                    image: Some(ImageReference {
+                        x: 1,
+                        y: 258,
+                        image_index: Some(ImgId(0)),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                time: Some(Time {
+                    minutes: Some(TimeNumbers {
+                        tens: Some(ImageRange {
+                            x: 16,
+                            y: 32,
+                            image_index: Some(ImgId(0)),
+                            images_count: Some(2),
+                            ..Default::default()
+                        }),
+                        ones: Some(ImageRange {
+                            x: 731,
+                            y: 12,
+                            image_index: Some(ImgId(1)),
+                            images_count: Some(7),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            images: vec![Image {
+                pixels: vec![0x11, 0x21, 0x31, 0xBE, 0x12, 0x22, 0x32, 0xBD],
+                width: 2,
+                height: 1,
+                bits_per_pixel: 32,
+                pixel_format: 0x10,
+            }],
+            source: None,
+        };
+
+        let bytes = bin_writer(&watchface).unwrap();
+        assert_eq!(&bytes[..4], b"UIHH");
+        assert_eq!(&bytes[4..79], &DEFAULT_HEADER);
+        assert_eq!(&bytes[79..83], &[0x17, 0x00, 0x00, 0x00]); // Size of biggest param: 23
+        assert_eq!(&bytes[83..87], &[0x12, 0x00, 0x00, 0x00]); // Size of params info: 18
+
+        let result: Watchface<MiBandParams> = bin_parser(Located::new(&bytes[..])).unwrap();
+        assert_eq!(
+            Watchface {
+                source: None,
+                ..result
+            },
+            watchface
+        );
+    }
+
+    // Xorshift generator, so random tests are reproducible and need no dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn bytes(&mut self, count: usize) -> Vec<u8> {
+            (0..count).map(|_| self.next() as u8).collect()
+        }
+
+        // Garbage between parts of a file
+        fn gap(&mut self, max_size: usize) -> Vec<u8> {
+            let size = self.below(max_size);
+            self.bytes(size)
+        }
+    }
+
+    fn random_param(rng: &mut Rng, depth: usize) -> Param {
+        match rng.below(if depth > 0 { 3 } else { 2 }) {
+            // Shift gives both small and big values
+            0 => Param::Number(rng.next() as i64 >> rng.below(64)),
+            1 => loop {
+                let float = f32::from_bits(rng.next() as u32);
+                if !float.is_nan() {
+                    break Param::Float(float);
+                }
+            },
+            _ => Param::Child(random_params(rng, depth - 1)),
+        }
+    }
+
+    fn random_params(rng: &mut Rng, depth: usize) -> Params {
+        let mut params = Params::new();
+        for _ in 0..rng.below(6) {
+            let key = rng.below(256) as u8;
+            let param = random_param(rng, depth);
+            params.push(key, param, None);
+        }
+        params
+    }
+
+    fn random_image(rng: &mut Rng) -> Image {
+        let width = 1 + rng.below(20) as u16;
+        let height = 1 + rng.below(20) as u16;
+        let pixels_count = width as usize * height as usize;
+        let (pixel_format, bits_per_pixel) = [
+            (0x64, 1),
+            (0x64, 2),
+            (0x64, 4),
+            (0x64, 8),
+            (0x08, 16),
+            (0x09, 16),
+            (0x1B, 16),
+            (0x1C, 16),
+            (0x13, 16),
+            (0x1B, 24),
+            (0x1C, 24),
+            (0x10, 32),
+            (0x65, 32),
+            (0xFFFF, 32),
+        ][rng.below(14)];
+
+        let mut pixels = vec![];
+        if pixel_format == 0x64 {
+            // Opaque colors and one transparent
+            let colors = (0..1usize << bits_per_pixel)
+                .map(|color_id| match color_id {
+                    0 => [rng.next() as u8, rng.next() as u8, rng.next() as u8, 0x00],
+                    _ => [rng.next() as u8, rng.next() as u8, rng.next() as u8, 0xFF],
+                })
+                .collect::<Vec<_>>();
+            for _ in 0..pixels_count {
+                pixels.extend(colors[rng.below(colors.len())]);
+            }
+        } else if pixel_format == 0x65 {
+            // Few colors to have repeated pixels
+            let colors = (0..3).map(|_| rng.bytes(4)).collect::<Vec<_>>();
+            for _ in 0..pixels_count {
+                pixels.extend(&colors[rng.below(colors.len())]);
+            }
+        } else {
+            for _ in 0..pixels_count {
+                let [red, green, blue, alpha] = [0; 4].map(|_| rng.next() as u8);
+                pixels.extend(match (pixel_format, bits_per_pixel) {
+                    (0x13, _) => [red & 0xF0, green & 0xF0, blue & 0xF0, alpha | 0x0F],
+                    (_, 16) => [red & 0xF8, green & 0xFC, blue & 0xF8, 0xFF],
+                    (_, 24) => [red & 0xF8, green & 0xFC, blue & 0xF8, alpha],
+                    _ => [red, green, blue, alpha],
+                });
+            }
+        }
+
+        Image {
+            pixels,
+            width,
+            height,
+            bits_per_pixel,
+            pixel_format,
+        }
+    }
+
+    // Varint with random count of not needed continuation bytes
+    fn write_padded_value(rng: &mut Rng, value: i64) -> Vec<u8> {
+        let mut res = write_variable_width_value(value, 64);
+        while res.len() < 10 && rng.below(3) == 0 {
+            *res.last_mut().unwrap() |= 0x80;
+            res.push(0x00);
+        }
+        res
+    }
+
+    // Writes params not the way params_writer does: keys are interleaved and varints are padded
+    fn write_shuffled_params(rng: &mut Rng, params: &Params) -> Vec<u8> {
+        let mut values = params
+            .iter()
+            .map(|(key, values)| (*key, values.iter().collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        values.sort_by_key(|(key, _)| *key);
+
+        let mut res = vec![];
+        while !values.is_empty() {
+            let index = rng.below(values.len());
+            let key = (values[index].0 as i64) << 3;
+            match values[index].1.remove(0) {
+                Param::Number(number) => {
+                    res.extend(write_padded_value(rng, key));
+                    res.extend(write_padded_value(rng, *number));
+                }
+                Param::Float(float) => {
+                    res.extend(write_padded_value(rng, key | 0x05));
+                    res.extend(float.to_le_bytes());
+                }
+                Param::Child(child) => {
+                    let child = write_shuffled_params(rng, child);
+                    res.extend(write_padded_value(rng, key | 0x02));
+                    res.extend(write_padded_value(rng, child.len() as i64));
+                    res.extend(child);
+                }
+            }
+            if values[index].1.is_empty() {
+                values.remove(index);
+            }
+        }
+        res
+    }
+
+    // Image with every pixel in its own packet, image_write joins them
+    fn write_uncompressed_packets(image: &Image) -> Vec<u8> {
+        let mut data = vec![];
+        for p in image.pixels.chunks(4) {
+            data.extend([0x00, p[0], p[1], p[2], 0xFF - p[3]]);
+        }
+        let mut res = image_write(image).unwrap()[..10].to_vec();
+        res.extend((data.len() as u32).to_le_bytes());
+        res.extend(data);
+        res
+    }
+
+    // 8 bit palette image with first two colors swapped, image_write keeps pixels order
+    fn write_swapped_palette(image: &Image) -> Vec<u8> {
+        let mut res = image_write(image).unwrap();
+        let palette_colors_count = u16::from_le_bytes([res[12], res[13]]) as usize;
+        if palette_colors_count < 2 {
+            return res;
+        }
+        let swap = |color_id: u8| match color_id {
+            0 => 1,
+            1 => 0,
+            color_id => color_id,
+        };
+        res[14] = swap(res[14].wrapping_sub(1)).wrapping_add(1);
+        let (first, second) = res[16..24].split_at_mut(4);
+        first.swap_with_slice(second);
+        for color_id in &mut res[16 + 4 * palette_colors_count..] {
+            *color_id = swap(*color_id);
+        }
+        res
+    }
+
+    // File with sections and images in random order, gaps between them and trailing bytes
+    fn random_bin(rng: &mut Rng) -> Vec<u8> {
+        let images_count = 1 + rng.below(4);
+        let background = Params::from([(
+            1,
+            vec![Param::Child(Params::from([
+                (1, vec![Param::Number(rng.below(126) as i64)]),
+                (2, vec![Param::Number(rng.below(294) as i64)]),
+                (3, vec![Param::Number(rng.below(images_count) as i64)]),
+            ]))],
+        )]);
+        // Sections 13, 16, 17 and 19 are unknown
+        let mut sections = vec![(2, write_shuffled_params(rng, &background))];
+        for key in [13, 16, 17, 19] {
+            if rng.below(2) == 0 {
+                let params = random_params(rng, 2);
+                sections.push((key, write_shuffled_params(rng, &params)));
+            }
+        }
+
+        let mut parameter_info = Params::new();
+        let mut parameters = vec![];
+        while !sections.is_empty() {
+            let (key, section) = sections.remove(rng.below(sections.len()));
+            parameters.extend(rng.gap(3));
+            parameter_info.insert(
+                key,
+                vec![Param::Child(Params::from([
+                    (1, vec![Param::Number(parameters.len() as i64)]),
+                    (2, vec![Param::Number(section.len() as i64)]),
+                ]))],
+            );
+            parameters.extend(section);
+        }
+        parameter_info.insert(
+            1,
+            vec![Param::Child(Params::from([
+                (1, vec![Param::Number(parameters.len() as i64)]),
+                (2, vec![Param::Number(images_count as i64)]),
+            ]))],
+        );
+        let parameter_info = write_shuffled_params(rng, &parameter_info);
+
+        let mut images_info = vec![0; 4 * images_count];
+        let mut images = vec![];
+        let mut order = (0..images_count).collect::<Vec<_>>();
+        for i in (1..images_count).rev() {
+            order.swap(i, rng.below(i + 1));
+        }
+        for index in order {
+            images.extend(rng.gap(3));
+            images_info[4 * index..4 * index + 4]
+                .copy_from_slice(&(images.len() as u32).to_le_bytes());
+            let mut image = random_image(rng);
+            if image.pixel_format == 0x65 {
+                images.extend(write_uncompressed_packets(&image));
+            } else if image.pixel_format == 0x64 {
+                image.bits_per_pixel = 8;
+                images.extend(write_swapped_palette(&image));
+            } else {
+                images.extend(image_write(&image).unwrap());
+            }
+        }
+
+        let mut header = DEFAULT_HEADER;
+        header[22..].copy_from_slice(&rng.bytes(53));
+        [
+            &SIGNATURE[..],
+            &header,
+            &(rng.next() as u32).to_le_bytes(),
+            &(parameter_info.len() as u32).to_le_bytes(),
+            &parameter_info,
+            &parameters,
+            &images_info,
+            &images,
+            &rng.gap(5),
+        ]
+        .concat()
+    }
+
+    // Numbers which are not written back the same when the field is re-encoded
+    fn random_field_value(rng: &mut Rng) -> Param {
+        let number = [0, 1, 2, 0xFF, 0xFF00, 0x00FF00FF, -1][rng.below(7)];
+        Param::Number(if rng.below(3) == 0 {
+            rng.below(300) as i64
+        } else {
+            number
+        })
+    }
+
+    // Params with interleaved keys, repeated values and keys which are not fields
+    fn random_struct_params(rng: &mut Rng, keys: &[u8], child_keys: &[u8]) -> Params {
+        let mut keys = keys.to_vec();
+        keys.extend([0, 9].iter().filter(|_| rng.below(4) == 0));
+        let mut values = vec![];
+        for key in keys {
+            if rng.below(4) == 0 {
+                continue;
+            }
+            for _ in 0..1 + rng.below(2) * rng.below(2) {
+                let value = match child_keys.contains(&key) {
+                    true => Param::Child(random_struct_params(rng, &[1, 2, 3], &[])),
+                    false => random_field_value(rng),
+                };
+                values.push((key, value));
+            }
+        }
+        for i in (1..values.len()).rev() {
+            values.swap(i, rng.below(i + 1));
+        }
+        let mut params = Params::new();
+        for (key, value) in values {
+            params.push(key, value, None);
+        }
+        params
+    }
+
+    #[test]
+    fn edited_field_keeps_other_fields() {
+        for seed in 1..=500 {
+            let rng = &mut Rng(seed);
+            let background = random_struct_params(rng, &[1, 2, 3, 4, 5], &[1, 3, 4, 5]);
+            let source = Params::from([(2, vec![Param::Child(background.clone())])]);
+            let mut parameters: Option<MiBandParams> = None;
+            parameters.transform(&[Param::Child(source)]).unwrap();
+
+            let edited_background = parameters.as_mut().unwrap().background.as_mut().unwrap();
+            let edited_key = match edited_background.image.as_mut() {
+                Some(image) if rng.below(2) == 0 => {
+                    image.x = image.x.wrapping_add(1);
+                    1
+                }
+                _ => {
+                    edited_background.color = Some(Color(1, 2, 3, 255));
+                    2
+                }
+            };
+            let result = match parameters.unwrap().to_param() {
+                Param::Child(params) => params,
+                _ => unreachable!(),
+            };
+            let result = match &result[&2][..] {
+                [Param::Child(params)] => params,
+                _ => panic!("seed {seed}: no background in {result:?}"),
+            };
+
+            let write = |params: &Params, key: u8| {
+                let values = params.get(&key).map_or(&[][..], |values| &values[..]);
+                let bytes = values.iter().map(|value| param_writer(key, value, 64));
+                bytes.collect::<Vec<_>>()
+            };
+            let keys = |params: &Params| {
+                let keys = params.ordered().map(|(key, _)| key);
+                keys.filter(|key| *key != edited_key).collect::<Vec<_>>()
+            };
+            assert_eq!(keys(result), keys(&background), "seed {seed}");
+            for key in background.keys().copied().filter(|key| *key != edited_key) {
+                assert_eq!(write(result, key), write(&background, key), "seed {seed}");
+            }
+            // Other fields of the edited image are kept too
+            if edited_key == 1 {
+                let (Param::Child(image), Param::Child(source_image)) =
+                    (&result[&1][0], &background[&1][0])
+                else {
+                    panic!("seed {seed}: image is not a child");
+                };
+                for key in [2, 3] {
+                    assert_eq!(write(image, key), write(source_image, key), "seed {seed}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn params_round_trip() {
+        for seed in 1..=500 {
+            let rng = &mut Rng(seed);
+            let params = random_params(rng, 3);
+
+            let bytes = params_writer(&params, 64);
+            let result = params_parser(&mut Located::new(&bytes[..]), bytes.len(), 64).unwrap();
+            assert_eq!(result, params, "seed {seed}");
+            assert_eq!(params_writer(&result, 64), bytes, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn param_round_trip() {
+        for seed in 1..=500 {
+            let rng = &mut Rng(seed);
+            let key = rng.below(256) as u8;
+            let param = random_param(rng, 2);
+
+            let bytes = param_writer(key, &param, 64);
+            let i = &mut Located::new(&bytes[..]);
+            assert_eq!(param_parser(i, 64).unwrap(), (key, param), "seed {seed}");
+            assert_eq!(i.location(), bytes.len(), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn shuffled_params_parse() {
+        for seed in 1..=500 {
+            let rng = &mut Rng(seed);
+            let params = random_params(rng, 3);
+
+            let bytes = write_shuffled_params(rng, &params);
+            let result = params_parser(&mut Located::new(&bytes[..]), bytes.len(), 64).unwrap();
+            assert_eq!(sorted(&result), sorted(&params), "seed {seed}");
+            // Interleaved keys are written back in the same order
+            let written = params_writer(&result, 64);
+            let parsed = params_parser(&mut Located::new(&written[..]), written.len(), 64);
+            assert_eq!(parsed.unwrap(), result, "seed {seed}");
+        }
+    }
+
+    // Params with values ordered by keys, as shuffled params are written in random order
+    fn sorted(params: &Params) -> Params {
+        let mut res = Params::new();
+        for (key, values) in params.iter() {
+            let values = values.iter().map(|value| match value {
+                Param::Child(child) => Param::Child(sorted(child)),
+                value => value.clone(),
+            });
+            res.insert(*key, values.collect());
+        }
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn params_equality_depends_on_order() {
+        let params = |values: [(u8, i64); 3]| {
+            let mut params = Params::new();
+            for (key, value) in values {
+                params.push(key, Param::Number(value), None);
+            }
+            params
+        };
+        let interleaved = params([(1, 1), (2, 2), (1, 3)]);
+        assert_ne!(interleaved, params([(1, 1), (1, 3), (2, 2)]));
+        assert_eq!(interleaved, params([(1, 1), (2, 2), (1, 3)]));
+        assert_eq!(
+            params_writer(&interleaved, 64),
+            [0x08, 0x01, 0x10, 0x02, 0x08, 0x03]
+        );
+    }
+
+    #[test]
+    fn image_round_trip() {
+        for seed in 1..=500 {
+            let rng = &mut Rng(seed);
+            let image = random_image(rng);
+
+            let bytes = image_write(&image).unwrap();
+            let result = image_parse(&mut Located::new(&bytes[..])).unwrap();
+            assert_eq!(result, image, "seed {seed}");
+            assert_eq!(image_write(&result).unwrap(), bytes, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn bin_round_trip() {
+        for seed in 1..=200 {
+            let rng = &mut Rng(seed);
+            let bytes = random_bin(rng);
+
+            let watchface: Watchface<MiBandParams> = bin_parser(Located::new(&bytes[..])).unwrap();
+            assert_eq!(bin_writer(&watchface).unwrap(), bytes, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn bin_round_trip_after_edit() {
+        for seed in 1..=200 {
+            let rng = &mut Rng(seed);
+            let bytes = random_bin(rng);
+
+            let mut watchface: Watchface<MiBandParams> =
+                bin_parser(Located::new(&bytes[..])).unwrap();
+            let background = watchface.parameters.as_mut().unwrap().background.as_mut();
+            background.unwrap().image.as_mut().unwrap().x += 1;
+            let index = rng.below(watchface.images.len());
+            watchface.images[index] = random_image(rng);
+
+            let edited = bin_writer(&watchface).unwrap();
+            let result: Watchface<MiBandParams> = bin_parser(Located::new(&edited[..])).unwrap();
+            assert_eq!(result.header, watchface.header, "seed {seed}");
+            assert_eq!(result.parameters, watchface.parameters, "seed {seed}");
+            assert_eq!(result.images, watchface.images, "seed {seed}");
+            // Not edited images and sections are kept as they were
+            let source = watchface.source.as_ref().unwrap();
+            let result_source = result.source.unwrap();
+            for (i, image) in result_source.encoded_images.iter().enumerate() {
+                if i != index {
+                    assert_eq!(image, &source.encoded_images[i], "seed {seed}");
+                }
+            }
+            for (key, bytes) in &source.sections {
+                if *key != 2 {
+                    let section = result_source.sections.iter().find(|(k, _)| k == key);
+                    assert_eq!(section, Some(&(*key, bytes.clone())), "seed {seed}");
+                }
+            }
+        }
+    }
+}
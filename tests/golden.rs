@@ -0,0 +1,240 @@
+// Every directory in tests/golden/<device> is a fixture face with:
+// - watchface.bin: face to parse
+// - watchface.json: expected params, for example from watchface-js
+// - preview.png: expected preview
+// - scenario.json: optional preview params, scenarios/default.json is used otherwise
+// Side by side diffs of failed previews are written to target/golden
+
+use {
+    image::{Rgba, RgbaImage},
+    serde_json::Value,
+    std::{fs, path::Path},
+    watchface_rs::{
+        parse_watch_face_bin, write_watch_face_bin, BipUParams, GtrParams, Gts2MiniParams,
+        GtsParams, MiBand4Params, MiBand6Params, MiBandParams, PreviewParams, Watchface,
+        WatchfaceError,
+    },
+};
+
+// Written watchface, its params as json and its preview
+type Rendered = (Result<Vec<u8>, WatchfaceError>, Value, RgbaImage);
+type Render = fn(&[u8], &PreviewParams) -> Result<Rendered, WatchfaceError>;
+
+macro_rules! render {
+    ($params:ty) => {
+        |bytes: &[u8], params: &PreviewParams| -> Result<Rendered, WatchfaceError> {
+            let watchface: Watchface<$params> = parse_watch_face_bin(&mut &bytes[..])?;
+            Ok((
+                write_watch_face_bin(&watchface),
+                serde_json::to_value(&watchface.parameters).unwrap(),
+                watchface.render_preview(params),
+            ))
+        }
+    };
+}
+
+fn device_render(device: &str) -> Option<Render> {
+    match device {
+        "bipu" => Some(render!(BipUParams)),
+        "gtr" => Some(render!(GtrParams)),
+        "gts" => Some(render!(GtsParams)),
+        "gts2mini" => Some(render!(Gts2MiniParams)),
+        "miband4" => Some(render!(MiBand4Params)),
+        "miband5" => Some(render!(MiBandParams)),
+        "miband6" => Some(render!(MiBand6Params)),
+        _ => None,
+    }
+}
+
+// Previews with lower PSNR are considered different
+const MIN_PSNR: f64 = 40.;
+
+// Paths of all values which differ between expected and actual json
+fn json_diff(path: &str, expected: &Value, actual: &Value, res: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected {
+                let path = format!("{path}.{key}");
+                match actual.get(key) {
+                    Some(actual) => json_diff(&path, value, actual, res),
+                    None => res.push(format!("{path}: missing")),
+                }
+            }
+            for key in actual.keys().filter(|key| !expected.contains_key(*key)) {
+                res.push(format!("{path}.{key}: unexpected"));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                json_diff(&format!("{path}[{i}]"), expected, actual, res);
+            }
+        }
+        // Numbers are compared as floats to not fail on 1 vs 1.0
+        (Value::Number(expected), Value::Number(actual))
+            if expected.as_f64() == actual.as_f64() => {}
+        _ if expected == actual => {}
+        _ => res.push(format!("{path}: expected {expected}, got {actual}")),
+    }
+}
+
+// Returns PSNR and count of different pixels
+fn compare_images(expected: &RgbaImage, actual: &RgbaImage) -> (f64, usize) {
+    let mut squared_error = 0.;
+    let mut different_pixels = 0;
+    for (expected, actual) in expected.pixels().zip(actual.pixels()) {
+        if expected != actual {
+            different_pixels += 1;
+        }
+        for c in 0..4 {
+            squared_error += (expected[c] as f64 - actual[c] as f64).powi(2);
+        }
+    }
+    let mse = squared_error / (expected.width() * expected.height() * 4) as f64;
+    let psnr = if mse == 0. {
+        f64::INFINITY
+    } else {
+        10. * (255. * 255. / mse).log10()
+    };
+    (psnr, different_pixels)
+}
+
+// Expected, differences in red and actual previews next to each other
+fn side_by_side_diff(expected: &RgbaImage, actual: &RgbaImage) -> RgbaImage {
+    let width = expected.width().max(actual.width());
+    let height = expected.height().max(actual.height());
+    let mut res = RgbaImage::from_pixel(width * 3, height, Rgba([0, 0, 0, 255]));
+    for (x, y, pixel) in expected.enumerate_pixels() {
+        res.put_pixel(x, y, *pixel);
+    }
+    for (x, y, pixel) in actual.enumerate_pixels() {
+        res.put_pixel(x + width * 2, y, *pixel);
+    }
+    for x in 0..width {
+        for y in 0..height {
+            let expected = expected.get_pixel_checked(x, y);
+            let actual = actual.get_pixel_checked(x, y);
+            let color = if expected == actual {
+                let value = expected.map_or(0, |pixel| pixel[0] / 4 + pixel[1] / 4 + pixel[2] / 4);
+                Rgba([value, value, value, 255])
+            } else {
+                Rgba([255, 0, 0, 255])
+            };
+            res.put_pixel(x + width, y, color);
+        }
+    }
+    res
+}
+
+fn check_face(dir: &Path, diff_dir: &Path, render: Render) -> Vec<String> {
+    let mut errors = vec![];
+    let device = dir.parent().unwrap().file_name().unwrap().to_str().unwrap();
+    let name = format!("{device}_{}", dir.file_name().unwrap().to_str().unwrap());
+
+    let scenario = if dir.join("scenario.json").exists() {
+        dir.join("scenario.json")
+    } else {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/default.json")
+    };
+    let params: PreviewParams =
+        serde_json::from_str(&fs::read_to_string(scenario).unwrap()).unwrap();
+
+    let bytes = fs::read(dir.join("watchface.bin")).unwrap();
+    let (written, actual, preview) = match render(&bytes, &params) {
+        Ok(rendered) => rendered,
+        Err(e) => return vec![format!("{name}: {e}")],
+    };
+
+    // Not edited face is written back as it was
+    if written.as_ref() != Ok(&bytes) {
+        errors.push(format!("{name}: written watchface differs from parsed one"));
+    }
+
+    let expected: Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("watchface.json")).unwrap()).unwrap();
+    let mut json_errors = vec![];
+    json_diff("", &expected, &actual, &mut json_errors);
+    errors.extend(json_errors.iter().map(|e| format!("{name}: {e}")));
+
+    let actual = preview;
+    let expected = image::open(dir.join("preview.png")).unwrap().into_rgba8();
+
+    let (psnr, different_pixels) = if expected.dimensions() == actual.dimensions() {
+        compare_images(&expected, &actual)
+    } else {
+        (0., (expected.width() * expected.height()) as usize)
+    };
+    if psnr < MIN_PSNR {
+        fs::create_dir_all(diff_dir).unwrap();
+        let diff_path = diff_dir.join(format!("{name}_diff.png"));
+        side_by_side_diff(&expected, &actual)
+            .save(&diff_path)
+            .unwrap();
+        errors.push(format!(
+            "{name}: preview PSNR is {psnr:.2}, {different_pixels} pixels differ, see {}",
+            diff_path.display()
+        ));
+    }
+
+    errors
+}
+
+#[test]
+fn golden_faces() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dirs = |path: &Path| {
+        let mut dirs = fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs
+    };
+
+    let mut errors = vec![];
+    for device_dir in dirs(&root.join("tests/golden")) {
+        let device = device_dir.file_name().unwrap().to_str().unwrap();
+        let Some(render) = device_render(device) else {
+            errors.push(format!("{device}: unknown device"));
+            continue;
+        };
+        for dir in dirs(&device_dir) {
+            errors.extend(check_face(&dir, &root.join("target/golden"), render));
+        }
+    }
+    assert!(errors.is_empty(), "{}", errors.join("\n"));
+}
+
+#[test]
+fn json_diff_paths() {
+    let expected = serde_json::json!({"Time": {"Hours": {"X": 1, "Y": 2}}, "Other": [1, 2]});
+    let actual = serde_json::json!({"Time": {"Hours": {"X": 1.0, "Z": 2}}, "Other": [1, 3]});
+
+    let mut res = vec![];
+    json_diff("", &expected, &actual, &mut res);
+    assert_eq!(
+        res,
+        [
+            ".Other[1]: expected 2, got 3",
+            ".Time.Hours.Y: missing",
+            ".Time.Hours.Z: unexpected"
+        ]
+    );
+}
+
+#[test]
+fn compare_and_diff_images() {
+    let expected = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 255]));
+    let mut actual = expected.clone();
+    assert_eq!(compare_images(&expected, &actual), (f64::INFINITY, 0));
+
+    actual.put_pixel(1, 1, Rgba([10, 20, 40, 255]));
+    let (psnr, different_pixels) = compare_images(&expected, &actual);
+    assert_eq!(different_pixels, 1);
+    assert!((psnr - 40.17).abs() < 0.01);
+
+    let diff = side_by_side_diff(&expected, &actual);
+    assert_eq!(diff.dimensions(), (6, 2));
+    assert_eq!(diff.get_pixel(3, 1), &Rgba([255, 0, 0, 255]));
+    assert_eq!(diff.get_pixel(5, 1), &Rgba([10, 20, 40, 255]));
+}